@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::{rngs::StdRng, Rng};
+
+use crate::{net::offline, Layer, PlayArea};
+
+// Arena mode fences the run in, so running in a straight line stops working. The walls
+// are static bodies on `Layer::World` that stop players, meteors and chickens alike,
+// and `spawn_chasers` brings meteors in from just inside them instead of off screen.
+// Like every other size in the game the arena is measured in play areas, so the
+// walls are rebuilt to fit whenever the window changes size.
+
+// Default size, in play areas across
+const DEFAULT_SIZE: f32 = 2.;
+pub const MIN_SIZE: f32 = 1.;
+pub const MAX_SIZE: f32 = 6.;
+// How many straight pieces make up a circular wall
+const CIRCLE_SEGMENTS: usize = 64;
+const WALL_COLOR: Color = Color::rgb(0.35, 0.6, 0.9);
+const WALL_Z: f32 = -0.02;
+// How many places `spawn_point` tries before going with the one furthest from the players
+const SPAWN_TRIES: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArenaShape {
+    Rectangle,
+    Circle,
+}
+
+impl ArenaShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArenaShape::Rectangle => "rectangle",
+            ArenaShape::Circle => "circle",
+        }
+    }
+}
+
+// Picked in the menu, see `menu_input`, or with `arena` in the console
+pub struct Arena {
+    pub shape: Option<ArenaShape>,
+    // How many play areas across. A rectangle is also this many play areas tall.
+    pub size: f32,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena {
+            shape: None,
+            size: DEFAULT_SIZE,
+        }
+    }
+}
+
+impl Arena {
+    // Half the width and height inside the walls. A circle's radius is the x.
+    pub fn half_extents(&self, play_area: Vec2) -> Vec2 {
+        match self.shape {
+            Some(ArenaShape::Circle) => Vec2::splat(play_area.x * self.size / 2.),
+            _ => play_area * self.size / 2.,
+        }
+    }
+
+    // Moves `position` in from the walls until something `margin` wide would fit there.
+    // Anything is fine when there's no arena.
+    pub fn keep_inside(&self, play_area: Vec2, position: Vec2, margin: f32) -> Vec2 {
+        let half_extents = (self.half_extents(play_area) - Vec2::splat(margin)).max(Vec2::ZERO);
+        match self.shape {
+            None => position,
+            Some(ArenaShape::Rectangle) => position.clamp(-half_extents, half_extents),
+            Some(ArenaShape::Circle) => position.clamp_length_max(half_extents.x),
+        }
+    }
+
+    pub fn contains(&self, play_area: Vec2, position: Vec2) -> bool {
+        self.keep_inside(play_area, position, 0.) == position
+    }
+
+    // A spot `margin` in from the walls, picked with the run's `RandomGenerator` so spawns stay the same in replays
+    pub fn spawn_point(&self, rng: &mut StdRng, play_area: Vec2, margin: f32, players: &[Vec2]) -> Vec2 {
+        let half_extents = (self.half_extents(play_area) - Vec2::splat(margin)).max(Vec2::ZERO);
+        let distance_to_players = |point: Vec2| {
+            players
+                .iter()
+                .map(|player| player.distance_squared(point))
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        let mut best = Vec2::ZERO;
+        let mut best_distance = f32::NEG_INFINITY;
+        for _ in 0..SPAWN_TRIES {
+            let point = match self.shape {
+                Some(ArenaShape::Circle) => {
+                    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                    Vec2::new(angle.cos(), angle.sin()) * half_extents.x
+                }
+                // Somewhere along the edge, picked by how far round it is
+                _ => {
+                    let (width, height) = (half_extents.x * 2., half_extents.y * 2.);
+                    let along = rng.gen_range(0.0..=(width + height) * 2.);
+                    if along < width {
+                        Vec2::new(along - half_extents.x, half_extents.y)
+                    } else if along < width + height {
+                        Vec2::new(half_extents.x, half_extents.y - (along - width))
+                    } else if along < width * 2. + height {
+                        Vec2::new(half_extents.x - (along - width - height), -half_extents.y)
+                    } else {
+                        Vec2::new(-half_extents.x, -half_extents.y + (along - width * 2. - height))
+                    }
+                }
+            };
+
+            let distance = distance_to_players(point);
+            if distance > best_distance {
+                best = point;
+                best_distance = distance;
+            }
+        }
+        best
+    }
+}
+
+#[derive(Component)]
+struct ArenaWall;
+
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Arena>()
+            .add_system(build_arena_walls.with_run_criteria(offline));
+    }
+}
+
+// Walls sit outside `half_extents`, so the space inside is exactly the arena's size
+fn build_arena_walls(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    play_area: Res<PlayArea>,
+    wall_query: Query<Entity, With<ArenaWall>>,
+) {
+    if !arena.is_changed() && !play_area.is_changed() {
+        return;
+    }
+
+    wall_query.iter().for_each(|e| commands.entity(e).despawn());
+
+    let half_extents = arena.half_extents(play_area.0);
+    let thickness = play_area.0.x / 40.;
+    if half_extents.x <= 0. {
+        return;
+    }
+
+    match arena.shape {
+        None => {}
+        Some(ArenaShape::Rectangle) => {
+            let outer = half_extents + Vec2::splat(thickness);
+            let walls = [
+                (Vec2::new(0., half_extents.y + thickness / 2.), Vec2::new(outer.x * 2., thickness)),
+                (Vec2::new(0., -half_extents.y - thickness / 2.), Vec2::new(outer.x * 2., thickness)),
+                (Vec2::new(half_extents.x + thickness / 2., 0.), Vec2::new(thickness, outer.y * 2.)),
+                (Vec2::new(-half_extents.x - thickness / 2., 0.), Vec2::new(thickness, outer.y * 2.)),
+            ];
+            for (center, size) in walls {
+                spawn_wall(&mut commands, center, size, 0.);
+            }
+        }
+        Some(ArenaShape::Circle) => {
+            let radius = half_extents.x + thickness / 2.;
+            // Slightly longer than they need to be, so there are no gaps between pieces
+            let length = std::f32::consts::TAU * radius / CIRCLE_SEGMENTS as f32 * 1.1;
+            for i in 0..CIRCLE_SEGMENTS {
+                let angle = i as f32 * std::f32::consts::TAU / CIRCLE_SEGMENTS as f32;
+                let center = Vec2::new(angle.cos(), angle.sin()) * radius;
+                spawn_wall(&mut commands, center, Vec2::new(thickness, length), angle);
+            }
+        }
+    }
+}
+
+fn spawn_wall(commands: &mut Commands, center: Vec2, size: Vec2, angle: f32) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: WALL_COLOR,
+                custom_size: Some(size),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: center.extend(WALL_Z),
+                rotation: Quat::from_rotation_z(angle),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ArenaWall)
+        .insert(RigidBody::Static)
+        .insert(CollisionShape::Cuboid {
+            half_extends: (size / 2.).extend(0.),
+            border_radius: None,
+        })
+        .insert(
+            CollisionLayers::new(Layer::World, Layer::Player)
+                .with_mask(Layer::Enemies)
+                .with_mask(Layer::Pickups),
+        );
+}
@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::{
+    camera::CameraView, chickens::ChickenCaught, is_enemy, menu::InMenu, GamePaused, PlayerDied, PlayerHit, RestartRun,
+};
+
+// Gameplay code never talks to the audio backend directly. It either sends `PlaySound`
+// or the sounds are picked up here from events the game already sends. Without the
+// `audio` feature only that event plumbing exists, so headless builds and tests never
+// need an audio device.
+
+// Meteors bumping into each other happen constantly, so only a few per second are heard
+const METEOR_HIT_COOLDOWN: f32 = 0.12;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoundEffect {
+    Hit,
+    HeartLost,
+    Death,
+    Restart,
+    Pause,
+    Pickup,
+    Pulse,
+}
+
+pub struct PlaySound(pub SoundEffect);
+
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<PlaySound>()
+            .add_system(queue_game_sounds.label("queue_game_sounds"));
+
+        #[cfg(feature = "audio")]
+        app
+            .add_plugin(bevy_kira_audio::AudioPlugin)
+            .add_startup_system(backend::load_sounds)
+            .add_system(backend::play_sounds.after("queue_game_sounds"))
+            .add_system(backend::update_volumes)
+            .add_system(backend::update_music);
+    }
+}
+
+fn queue_game_sounds(
+    mut sounds: EventWriter<PlaySound>,
+    mut hits: EventReader<PlayerHit>,
+    mut restarts: EventReader<RestartRun>,
+    mut chickens: EventReader<ChickenCaught>,
+    mut collisions: EventReader<CollisionEvent>,
+    mut meteor_hit_cooldown: Local<f32>,
+    mut was_paused: Local<bool>,
+    time: Res<Time>,
+    view: Res<CameraView>,
+    player_died: Res<PlayerDied>,
+    game_paused: Res<GamePaused>,
+    in_menu: Res<InMenu>,
+    transform_query: Query<&Transform>,
+) {
+    for _ in hits.iter() {
+        sounds.send(PlaySound(SoundEffect::HeartLost));
+    }
+
+    if chickens.iter().count() > 0 {
+        sounds.send(PlaySound(SoundEffect::Pickup));
+    }
+
+    // Quitting to the menu also restarts, to clear the run away, but that's not worth a sound
+    if restarts.iter().count() > 0 && !in_menu.0 {
+        sounds.send(PlaySound(SoundEffect::Restart));
+    }
+
+    if player_died.is_changed() && player_died.0 {
+        sounds.send(PlaySound(SoundEffect::Death));
+    }
+
+    // `GamePaused` is rewritten on every restart, so compare against the last value seen
+    if game_paused.0 != *was_paused {
+        if !in_menu.0 && !player_died.0 {
+            sounds.send(PlaySound(SoundEffect::Pause));
+        }
+        *was_paused = game_paused.0;
+    }
+
+    *meteor_hit_cooldown -= time.delta_seconds();
+    for event in collisions.iter() {
+        if !event.is_started() || *meteor_hit_cooldown > 0. {
+            continue;
+        }
+
+        let (layers_1, layers_2) = event.collision_layers();
+        if !is_enemy(layers_1) || !is_enemy(layers_2) {
+            continue;
+        }
+
+        // Only meteors the player can actually see
+        let (entity, _) = event.rigid_body_entities();
+        if let Ok(transform) = transform_query.get(entity) {
+            let offset = transform.translation.truncate() - view.center;
+            if offset.x.abs() <= view.half_extents.x && offset.y.abs() <= view.half_extents.y {
+                sounds.send(PlaySound(SoundEffect::Hit));
+                *meteor_hit_cooldown = METEOR_HIT_COOLDOWN;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+mod backend {
+    use bevy::prelude::*;
+    use bevy_kira_audio::{Audio, AudioChannel, AudioSource};
+
+    use super::{PlaySound, SoundEffect};
+    use crate::{menu::InMenu, settings::Settings, ChaserCount, GamePaused, PlayerDied};
+
+    // Music is split into stems that all loop together. The base plays all the time and
+    // the others fade in once this many chasers are alive.
+    const MUSIC_LAYERS: [(&str, u32, u32); 3] = [
+        ("audio/music_base.ogg", 0, 0),
+        ("audio/music_drums.ogg", 50, 200),
+        ("audio/music_lead.ogg", 300, 600),
+    ];
+
+    pub struct SoundHandles {
+        effects: Vec<(SoundEffect, Handle<AudioSource>)>,
+    }
+
+    pub struct AudioChannels {
+        sfx: AudioChannel,
+        music: Vec<AudioChannel>,
+    }
+
+    pub fn load_sounds(mut commands: Commands, asset_server: Res<AssetServer>, audio: Res<Audio>) {
+        let effects = [
+            (SoundEffect::Hit, "audio/hit.ogg"),
+            (SoundEffect::HeartLost, "audio/heart_lost.ogg"),
+            (SoundEffect::Death, "audio/death.ogg"),
+            (SoundEffect::Restart, "audio/restart.ogg"),
+            (SoundEffect::Pause, "audio/pause.ogg"),
+            (SoundEffect::Pickup, "audio/pickup.ogg"),
+            (SoundEffect::Pulse, "audio/pulse.ogg"),
+        ];
+
+        let channels = AudioChannels {
+            sfx: AudioChannel::new("sfx".to_string()),
+            music: (0..MUSIC_LAYERS.len())
+                .map(|i| AudioChannel::new(format!("music_{}", i)))
+                .collect(),
+        };
+
+        // Every stem starts at the same time so they stay in sync, the upper ones silent
+        for (i, (path, _, _)) in MUSIC_LAYERS.iter().enumerate() {
+            if i > 0 {
+                audio.set_volume_in_channel(0., &channels.music[i]);
+            }
+            audio.play_looped_in_channel(asset_server.load(*path), &channels.music[i]);
+        }
+
+        commands.insert_resource(SoundHandles {
+            effects: effects
+                .iter()
+                .map(|(effect, path)| (*effect, asset_server.load(*path)))
+                .collect(),
+        });
+        commands.insert_resource(channels);
+    }
+
+    pub fn play_sounds(
+        mut sounds: EventReader<PlaySound>,
+        audio: Res<Audio>,
+        handles: Res<SoundHandles>,
+        channels: Res<AudioChannels>,
+    ) {
+        for PlaySound(effect) in sounds.iter() {
+            if let Some((_, handle)) = handles.effects.iter().find(|(e, _)| e == effect) {
+                audio.play_in_channel(handle.clone(), &channels.sfx);
+            }
+        }
+    }
+
+    pub fn update_volumes(settings: Res<Settings>, audio: Res<Audio>, channels: Res<AudioChannels>) {
+        if settings.is_changed() {
+            audio.set_volume_in_channel(settings.master_volume * settings.sfx_volume, &channels.sfx);
+        }
+    }
+
+    pub fn update_music(
+        audio: Res<Audio>,
+        channels: Res<AudioChannels>,
+        settings: Res<Settings>,
+        chaser_count: Res<ChaserCount>,
+        game_paused: Res<GamePaused>,
+        in_menu: Res<InMenu>,
+        player_died: Res<PlayerDied>,
+        time: Res<Time>,
+        // (smoothed volume, volume last sent to the backend) for each layer
+        mut layer_volumes: Local<Vec<(f32, f32)>>,
+        mut music_paused: Local<bool>,
+    ) {
+        // The menu keeps playing music, only a paused run goes quiet
+        let should_pause = game_paused.0 && !in_menu.0 && !player_died.0;
+        if should_pause != *music_paused {
+            for channel in channels.music.iter() {
+                if should_pause {
+                    audio.pause_channel(channel);
+                } else {
+                    audio.resume_channel(channel);
+                }
+            }
+            *music_paused = should_pause;
+        }
+
+        if layer_volumes.len() != MUSIC_LAYERS.len() {
+            *layer_volumes = vec![(0., 0.); MUSIC_LAYERS.len()];
+        }
+
+        let music_volume = settings.master_volume * settings.music_volume;
+        let fade = (time.delta_seconds() * 0.5).min(1.);
+
+        for (i, (_, start, full)) in MUSIC_LAYERS.iter().enumerate() {
+            let intensity = if full > start {
+                ((chaser_count.current.saturating_sub(*start)) as f32 / (full - start) as f32).min(1.)
+            } else {
+                1.
+            };
+            let target = intensity * music_volume;
+
+            // Fade rather than jump, and only bother the backend when the change is audible
+            let (smoothed, sent) = &mut layer_volumes[i];
+            *smoothed += (target - *smoothed) * fade;
+            if (*smoothed - *sent).abs() > 0.01 {
+                audio.set_volume_in_channel(*smoothed, &channels.music[i]);
+                *sent = *smoothed;
+            }
+        }
+    }
+}
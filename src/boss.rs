@@ -0,0 +1,549 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    ui::Val::Px,
+    utils::BoxedFuture,
+};
+use heron::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    arena::Arena,
+    command_line_value,
+    coop::player_bounds,
+    difficulty::Difficulty,
+    net::offline,
+    particles::{ParticleBurst, ParticleEffect},
+    replay::GameTick,
+    show_chaser_count, spawn_chaser, BoldFont, ChaserCount, ChaserSprite, Downed, EnemyCountText, PlayArea, Player,
+    RestartRun, SizeScale,
+};
+
+// Every so often a boss turns up: one huge meteor with its own health and a bar at the
+// bottom of the screen. It chases like any other meteor, so it's hurt by whatever it
+// runs into, other meteors included, and luring the swarm into it is how it gets
+// worn down. What each boss does is read from `assets/data/meteors.bosses.ron` rather
+// than written here, loaded through the asset server the same way as `enemies`.
+//
+// Bosses only count game time from `GameTick`, so they turn up in replays exactly
+// where they did in the run. `bosses off` in the console, or a `--script` starting
+// with it, leaves them out of a run entirely.
+
+const DEFINITIONS_PATH: &str = "data/meteors.bosses.ron";
+// Seconds into a run before the first boss, and between one going down and the next
+const FIRST_BOSS_AFTER: f32 = 45.;
+const BOSS_INTERVAL: f32 = 60.;
+// A boss in the middle of the swarm gets bumped constantly, so hits this close together only count once
+const HIT_COOLDOWN: f32 = 0.3;
+// How fast the meteors a boss throws out start moving
+const MINION_SPEED: f32 = 250.;
+const BAR_COLOR: Color = Color::rgb(0.9, 0.25, 0.2);
+
+#[derive(Deserialize, Clone)]
+pub struct BossDefinition {
+    pub name: String,
+    pub size_scale: f32,
+    pub health: u32,
+    // Multiplies the difficulty's chaser speed
+    pub speed: f32,
+    pub color: (f32, f32, f32),
+    pub patterns: Vec<BossPattern>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum BossPattern {
+    SplitOnHit { count: u32, size_scale: f32 },
+    Charge { interval: f32, speed: f32 },
+    MinionRing { interval: f32, count: u32, size_scale: f32 },
+}
+
+#[derive(TypeUuid)]
+#[uuid = "2d8f4c7a-9b3e-4f61-a5d2-7c0e1b9f3a64"]
+pub struct BossRegistry {
+    pub definitions: Vec<BossDefinition>,
+}
+
+#[derive(Default)]
+struct BossRegistryLoader;
+
+impl AssetLoader for BossRegistryLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definitions: Vec<BossDefinition> = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(BossRegistry { definitions }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bosses.ron"]
+    }
+}
+
+pub struct Bosses {
+    pub registry: Handle<BossRegistry>,
+    // Copied out of `registry` whenever it loads, empty until then so no boss turns up
+    pub definitions: Vec<BossDefinition>,
+    pub enabled: bool,
+    // Game time until the next boss, only counting down while there isn't one
+    countdown: f32,
+    next: usize,
+}
+
+#[derive(Component)]
+pub struct Boss {
+    pub definition: usize,
+    pub health: u32,
+    // Seconds until each of the definition's patterns goes off again
+    pattern_timers: Vec<f32>,
+    invulnerable: f32,
+    hits: u32,
+}
+
+// Brings in the next boss right away, or the one with this name
+pub struct SpawnBoss(pub Option<String>);
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<BossRegistry>()
+            .init_asset_loader::<BossRegistryLoader>()
+            .insert_resource(Bosses {
+                registry: Handle::default(),
+                definitions: Vec::new(),
+                enabled: true,
+                countdown: FIRST_BOSS_AFTER,
+                next: 0,
+            })
+            .add_event::<SpawnBoss>()
+            .add_startup_system(load_boss_registry)
+            .add_system(sync_boss_definitions)
+            .add_system(reset_bosses)
+            .add_system(schedule_bosses.with_run_criteria(offline))
+            .add_system(spawn_bosses.with_run_criteria(offline))
+            .add_system(run_boss_patterns.with_run_criteria(offline))
+            .add_system(damage_bosses.with_run_criteria(offline))
+            .add_system(sync_boss_bar.label("sync_boss_bar"))
+            .add_system(update_boss_bar.after("sync_boss_bar"));
+    }
+}
+
+// `--bosses <path>` swaps in another list from under `assets`, which is handy for trying out new ones
+fn load_boss_registry(asset_server: Res<AssetServer>, mut bosses: ResMut<Bosses>) {
+    let path = command_line_value("--bosses").unwrap_or_else(|| DEFINITIONS_PATH.to_string());
+    bosses.registry = asset_server.load(path.as_str());
+}
+
+fn sync_boss_definitions(
+    mut registry_events: EventReader<AssetEvent<BossRegistry>>,
+    registries: Res<Assets<BossRegistry>>,
+    mut bosses: ResMut<Bosses>,
+) {
+    for event in registry_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        if *handle != bosses.registry {
+            continue;
+        }
+
+        if let Some(registry) = registries.get(handle) {
+            if registry.definitions.is_empty() {
+                warn!("The boss list has no bosses in it");
+            }
+            info!("Loaded {} bosses", registry.definitions.len());
+            bosses.definitions = registry.definitions.clone();
+        }
+    }
+}
+
+// Meteors thrown out evenly around `center`, the first one at `angle` radians
+struct MeteorRing {
+    center: Vec2,
+    distance: f32,
+    angle: f32,
+    count: u32,
+    size_scale: f32,
+    speed: f32,
+}
+
+fn spawn_ring(
+    commands: &mut Commands,
+    texture: &Handle<Image>,
+    chaser_count: &mut ChaserCount,
+    window_width: f32,
+    ring: MeteorRing,
+) {
+    for i in 0..ring.count {
+        if chaser_count.at_max() {
+            break;
+        }
+
+        let angle = ring.angle + i as f32 * std::f32::consts::TAU / ring.count as f32;
+        let direction = Vec2::new(angle.cos(), angle.sin());
+        let meteor = spawn_chaser(
+            commands,
+            texture.clone(),
+            ring.center + direction * ring.distance,
+            ring.size_scale,
+            ring.speed,
+            window_width,
+        );
+        commands
+            .entity(meteor)
+            .insert(Velocity::from_linear((direction * MINION_SPEED).extend(0.)));
+        chaser_count.current += 1;
+    }
+}
+
+fn reset_bosses(mut restart_events: EventReader<RestartRun>, mut bosses: ResMut<Bosses>) {
+    if restart_events.iter().count() > 0 {
+        bosses.countdown = FIRST_BOSS_AFTER;
+        bosses.next = 0;
+    }
+}
+
+fn schedule_bosses(
+    tick: Res<GameTick>,
+    mut bosses: ResMut<Bosses>,
+    mut spawn_events: EventWriter<SpawnBoss>,
+    boss_query: Query<(), With<Boss>>,
+) {
+    if !bosses.enabled || !tick.running || boss_query.iter().next().is_some() {
+        return;
+    }
+
+    bosses.countdown -= tick.delta.as_secs_f32();
+    if bosses.countdown <= 0. {
+        bosses.countdown = BOSS_INTERVAL;
+        spawn_events.send(SpawnBoss(None));
+    }
+}
+
+fn spawn_bosses(
+    mut commands: Commands,
+    mut spawn_events: EventReader<SpawnBoss>,
+    mut bosses: ResMut<Bosses>,
+    (chaser_sprite, difficulty, play_area): (Res<ChaserSprite>, Res<Difficulty>, Res<PlayArea>),
+    arena: Res<Arena>,
+    mut chaser_count: ResMut<ChaserCount>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+    player_query: Query<&Transform, (With<Player>, Without<Downed>)>,
+) {
+    for SpawnBoss(name) in spawn_events.iter() {
+        let index = match name {
+            Some(name) => match bosses.definitions.iter().position(|d| d.name.eq_ignore_ascii_case(name)) {
+                Some(index) => index,
+                None => {
+                    warn!("There is no boss called {}", name);
+                    continue;
+                }
+            },
+            // Nothing to spawn until the list has loaded
+            None if bosses.definitions.is_empty() => continue,
+            None => {
+                let index = bosses.next % bosses.definitions.len();
+                bosses.next += 1;
+                index
+            }
+        };
+
+        let center = match player_bounds(player_query.iter().map(|t| t.translation.truncate())) {
+            Some((center, _)) => center,
+            None => continue,
+        };
+
+        // Comes in from just above the top of the screen, so there's a moment to see it
+        // coming. In an arena that might be past the wall, so it comes from the wall instead.
+        let definition = &bosses.definitions[index];
+        let window_width = play_area.0.x;
+        let size = window_width / 40. * definition.size_scale;
+        let position = arena.keep_inside(play_area.0, center + Vec2::new(0., play_area.0.y / 2. + size), size);
+
+        let boss = spawn_chaser(
+            &mut commands,
+            chaser_sprite.0.clone(),
+            position,
+            definition.size_scale,
+            difficulty.settings().chaser_speed * definition.speed,
+            window_width,
+        );
+        let (red, green, blue) = definition.color;
+        commands
+            .entity(boss)
+            .insert(Sprite {
+                color: Color::rgb(red, green, blue),
+                custom_size: Some(Vec2::new(size, size)),
+                ..Default::default()
+            })
+            .insert(Boss {
+                definition: index,
+                health: definition.health,
+                pattern_timers: definition
+                    .patterns
+                    .iter()
+                    .map(|pattern| match pattern {
+                        BossPattern::Charge { interval, .. } | BossPattern::MinionRing { interval, .. } => *interval,
+                        BossPattern::SplitOnHit { .. } => 0.,
+                    })
+                    .collect(),
+                invulnerable: 0.,
+                hits: 0,
+            });
+
+        chaser_count.current += 1;
+        show_chaser_count(&chaser_count, enemy_count_text_query.iter_mut());
+        info!("Boss {} appeared", definition.name);
+    }
+}
+
+fn run_boss_patterns(
+    mut commands: Commands,
+    tick: Res<GameTick>,
+    bosses: Res<Bosses>,
+    (chaser_sprite, difficulty, play_area): (Res<ChaserSprite>, Res<Difficulty>, Res<PlayArea>),
+    mut chaser_count: ResMut<ChaserCount>,
+    mut boss_query: Query<(&mut Boss, &Transform, &SizeScale, &mut Velocity), Without<Player>>,
+    player_query: Query<&Transform, (With<Player>, Without<Downed>)>,
+) {
+    if !tick.running {
+        return;
+    }
+
+    let delta = tick.delta.as_secs_f32();
+    let window_width = play_area.0.x;
+
+    for (mut boss, transform, SizeScale(size_scale), mut velocity) in boss_query.iter_mut() {
+        boss.invulnerable = (boss.invulnerable - delta).max(0.);
+        let position = transform.translation.truncate();
+        // The list can shrink under a live boss when it's edited while the game runs
+        let definition = match bosses.definitions.get(boss.definition) {
+            Some(definition) => definition,
+            None => continue,
+        };
+
+        for (pattern, timer) in definition.patterns.iter().zip(boss.pattern_timers.iter_mut()) {
+            let interval = match pattern {
+                BossPattern::Charge { interval, .. } | BossPattern::MinionRing { interval, .. } => *interval,
+                BossPattern::SplitOnHit { .. } => continue,
+            };
+            *timer -= delta;
+            if *timer > 0. {
+                continue;
+            }
+            *timer += interval;
+
+            match *pattern {
+                BossPattern::Charge { speed, .. } => {
+                    let nearest = player_query.iter().map(|t| t.translation.truncate()).min_by(|a, b| {
+                        a.distance_squared(position).partial_cmp(&b.distance_squared(position)).unwrap()
+                    });
+                    if let Some(target) = nearest {
+                        let direction = (target - position).normalize_or_zero();
+                        velocity.linear = (direction * speed).extend(0.);
+                    }
+                }
+                BossPattern::MinionRing { count, size_scale: minion_scale, .. } => spawn_ring(
+                    &mut commands,
+                    &chaser_sprite.0,
+                    &mut chaser_count,
+                    window_width,
+                    MeteorRing {
+                        center: position,
+                        distance: window_width / 80. * (size_scale + minion_scale * 2.),
+                        angle: 0.,
+                        count,
+                        size_scale: minion_scale,
+                        speed: difficulty.settings().chaser_speed,
+                    },
+                ),
+                BossPattern::SplitOnHit { .. } => {}
+            }
+        }
+    }
+}
+
+// Anything a boss runs into hurts it, players and other meteors alike
+fn damage_bosses(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    bosses: Res<Bosses>,
+    (chaser_sprite, difficulty, play_area): (Res<ChaserSprite>, Res<Difficulty>, Res<PlayArea>),
+    mut chaser_count: ResMut<ChaserCount>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+    mut boss_query: Query<(&mut Boss, &Transform, &SizeScale)>,
+    mut bursts: EventWriter<ParticleBurst>,
+) {
+    let window_width = play_area.0.x;
+
+    for event in events.iter().filter(|event| event.is_started()) {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        for entity in [entity_1, entity_2] {
+            let (mut boss, transform, SizeScale(size_scale)) = match boss_query.get_mut(entity) {
+                Ok(boss) if boss.0.health > 0 && boss.0.invulnerable <= 0. => boss,
+                _ => continue,
+            };
+
+            boss.health -= 1;
+            boss.hits += 1;
+            boss.invulnerable = HIT_COOLDOWN;
+            let position = transform.translation.truncate();
+            let definition = match bosses.definitions.get(boss.definition) {
+                Some(definition) => definition,
+                None => continue,
+            };
+
+            if boss.health == 0 {
+                commands.entity(entity).despawn();
+                chaser_count.current = chaser_count.current.saturating_sub(1);
+                bursts.send(ParticleBurst {
+                    effect: ParticleEffect::Death,
+                    position,
+                    scale: 2.,
+                });
+                info!("Boss {} was defeated", definition.name);
+                continue;
+            }
+
+            for pattern in definition.patterns.iter() {
+                if let BossPattern::SplitOnHit { count, size_scale: piece_scale } = *pattern {
+                    spawn_ring(
+                        &mut commands,
+                        &chaser_sprite.0,
+                        &mut chaser_count,
+                        window_width,
+                        MeteorRing {
+                            center: position,
+                            distance: window_width / 80. * (size_scale + piece_scale * 2.),
+                            // Turned a little on every hit so the pieces don't always leave the same way
+                            angle: boss.hits as f32 * 2.4,
+                            count,
+                            size_scale: piece_scale,
+                            speed: difficulty.settings().chaser_speed,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    if chaser_count.is_changed() {
+        show_chaser_count(&chaser_count, enemy_count_text_query.iter_mut());
+    }
+}
+
+#[derive(Component)]
+struct BossBarRoot;
+
+#[derive(Component)]
+struct BossBarName;
+
+#[derive(Component)]
+struct BossBarFill;
+
+fn sync_boss_bar(
+    mut commands: Commands,
+    bold_font: Res<BoldFont>,
+    boss_query: Query<(), With<Boss>>,
+    root_query: Query<Entity, With<BossBarRoot>>,
+) {
+    let boss_alive = boss_query.iter().next().is_some();
+    let spawned = root_query.iter().next().is_some();
+
+    if boss_alive && !spawned {
+        spawn_boss_bar(&mut commands, bold_font.0.clone());
+    } else if !boss_alive && spawned {
+        root_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    }
+}
+
+fn spawn_boss_bar(commands: &mut Commands, font: Handle<Font>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(40.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Px(24.0),
+                    left: Val::Percent(30.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(BossBarRoot)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font,
+                            font_size: 28.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(BossBarName);
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Px(16.0)),
+                        ..Default::default()
+                    },
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                ..Default::default()
+                            },
+                            color: BAR_COLOR.into(),
+                            ..Default::default()
+                        })
+                        .insert(BossBarFill);
+                });
+        });
+}
+
+// Shows the first boss if the console brought in more than one
+fn update_boss_bar(
+    bosses: Res<Bosses>,
+    boss_query: Query<&Boss>,
+    mut name_query: Query<&mut Text, With<BossBarName>>,
+    mut fill_query: Query<&mut Style, With<BossBarFill>>,
+) {
+    let boss = match boss_query.iter().next() {
+        Some(boss) => boss,
+        None => return,
+    };
+    let definition = match bosses.definitions.get(boss.definition) {
+        Some(definition) => definition,
+        None => return,
+    };
+
+    for mut text in name_query.iter_mut() {
+        text.sections[0].value = definition.name.clone();
+    }
+    for mut style in fill_query.iter_mut() {
+        style.size.width = Val::Percent(100. * boss.health as f32 / definition.health as f32);
+    }
+}
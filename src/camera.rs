@@ -0,0 +1,207 @@
+use bevy::{prelude::*, render::camera::OrthographicProjection};
+use heron::prelude::*;
+
+use crate::{
+    controls::InputAction, coop::player_bounds, menu::InMenu, settings::Settings, Camera2D, ChaserCount, Downed,
+    GamePaused, Player, PlayerDied, PlayerHit, RestartRun,
+};
+
+// The camera runs on real time and ignores `GamePaused`, so it keeps settling and can
+// be panned around while the game is paused. Everything that needs to know what is on
+// screen should read `CameraView` instead of the window size, since the view zooms out.
+
+pub struct CameraSettings {
+    // Angular frequency of the critically damped follow. Higher is snappier.
+    pub follow_stiffness: f32,
+    // How many seconds ahead of the player's velocity the camera aims
+    pub look_ahead_time: f32,
+    // Look-ahead is capped to this fraction of the view so the player never leaves it
+    pub max_look_ahead: f32,
+    // 0 turns screen shake off entirely
+    pub shake_intensity: f32,
+    pub max_shake_offset: f32,
+    pub max_shake_angle: f32,
+    // Trauma lost per second
+    pub trauma_decay: f32,
+    // Zoom reached when the chaser count hits its cap
+    pub max_zoom: f32,
+    // In co-op the camera zooms out to keep every player on screen, leaving this fraction
+    // of the window around them, but never further out than `max_fit_zoom`
+    pub fit_margin: f32,
+    pub max_fit_zoom: f32,
+    pub pan_speed: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            follow_stiffness: 6.,
+            look_ahead_time: 0.35,
+            max_look_ahead: 0.25,
+            shake_intensity: 1.,
+            max_shake_offset: 30.,
+            max_shake_angle: 0.05,
+            trauma_decay: 1.5,
+            max_zoom: 1.5,
+            fit_margin: 0.35,
+            max_fit_zoom: 3.,
+            pan_speed: 900.,
+        }
+    }
+}
+
+// The part of the world currently on screen, after zoom and shake
+pub struct CameraView {
+    pub center: Vec2,
+    pub half_extents: Vec2,
+    pub zoom: f32,
+}
+
+// Any system can add trauma to shake the camera. Shake grows with the square of trauma.
+#[derive(Default)]
+pub struct CameraTrauma(pub f32);
+
+impl CameraTrauma {
+    pub fn add(&mut self, amount: f32) {
+        self.0 = (self.0 + amount).min(1.);
+    }
+}
+
+#[derive(Default)]
+struct CameraFollow {
+    position: Vec2,
+    velocity: Vec2,
+    pan: Vec2,
+    zoom: f32,
+    shake_time: f32,
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CameraSettings>()
+            .init_resource::<CameraTrauma>()
+            .insert_resource(CameraFollow { zoom: 1., ..Default::default() })
+            .insert_resource(CameraView { center: Vec2::ZERO, half_extents: Vec2::ZERO, zoom: 1. })
+            .add_system(add_damage_trauma.before("camera"))
+            .add_system(update_camera.label("camera"));
+    }
+}
+
+fn add_damage_trauma(
+    mut hits: EventReader<PlayerHit>,
+    player_died: Res<PlayerDied>,
+    mut trauma: ResMut<CameraTrauma>,
+) {
+    for _ in hits.iter() {
+        trauma.add(0.35);
+    }
+
+    if player_died.is_changed() && player_died.0 {
+        trauma.add(1.);
+    }
+}
+
+fn update_camera(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    game_settings: Res<Settings>,
+    mut follow: ResMut<CameraFollow>,
+    mut trauma: ResMut<CameraTrauma>,
+    mut view: ResMut<CameraView>,
+    mut restart_events: EventReader<RestartRun>,
+    keyboard_input: Res<Input<KeyCode>>,
+    (game_paused, in_menu): (Res<GamePaused>, Res<InMenu>),
+    chaser_count: Res<ChaserCount>,
+    windows: Res<Windows>,
+    player_query: Query<(&Transform, &Velocity), (With<Player>, Without<Downed>, Without<Camera2D>)>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), (With<Camera2D>, Without<Player>)>,
+) {
+    let delta = time.delta_seconds();
+    let window = windows.get_primary().unwrap();
+    let window_half_extents = Vec2::new(window.width(), window.height()) / 2.;
+    // Follow the middle of every living player, moving at their average velocity
+    let (player, player_spread) = player_bounds(player_query.iter().map(|(t, _)| t.translation.truncate()))
+        .unwrap_or((follow.position, Vec2::ZERO));
+    let player_velocity = player_query.iter().map(|(_, v)| v.linear.truncate()).sum::<Vec2>()
+        / player_query.iter().count().max(1) as f32;
+
+    if restart_events.iter().count() > 0 {
+        follow.position = player;
+        follow.velocity = Vec2::ZERO;
+        follow.pan = Vec2::ZERO;
+        trauma.0 = 0.;
+    }
+
+    // Free look while paused with the movement keys, which springs back once the game
+    // resumes. The menu is paused too, but uses the same keys to pick a difficulty.
+    if game_paused.0 && !in_menu.0 {
+        let bindings = &game_settings.key_bindings;
+        let mut direction = Vec2::ZERO;
+        if bindings.pressed(InputAction::MoveLeft, &keyboard_input) {
+            direction.x -= 1.;
+        }
+        if bindings.pressed(InputAction::MoveRight, &keyboard_input) {
+            direction.x += 1.;
+        }
+        if bindings.pressed(InputAction::MoveUp, &keyboard_input) {
+            direction.y += 1.;
+        }
+        if bindings.pressed(InputAction::MoveDown, &keyboard_input) {
+            direction.y -= 1.;
+        }
+        follow.pan += direction * settings.pan_speed * follow.zoom * delta;
+    } else {
+        follow.pan = Vec2::ZERO;
+    }
+
+    let chaser_zoom = 1. + (settings.max_zoom - 1.) * (chaser_count.current as f32 / chaser_count.max as f32).min(1.);
+    let fit = player_spread / window_half_extents + Vec2::splat(settings.fit_margin);
+    let fit_zoom = fit.x.max(fit.y).min(settings.max_fit_zoom);
+    let target_zoom = chaser_zoom.max(fit_zoom);
+    follow.zoom += (target_zoom - follow.zoom) * (1. - (-delta).exp());
+
+    let max_look_ahead = window_half_extents * follow.zoom * settings.max_look_ahead;
+    let look_ahead = (player_velocity * settings.look_ahead_time)
+        .clamp(-max_look_ahead, max_look_ahead);
+    let target = player + look_ahead + follow.pan;
+
+    let (position, velocity) = smooth_damp(follow.position, target, follow.velocity, settings.follow_stiffness, delta);
+    follow.position = position;
+    follow.velocity = velocity;
+
+    trauma.0 = (trauma.0 - settings.trauma_decay * delta).max(0.);
+    follow.shake_time += delta;
+    let shake = trauma.0 * trauma.0 * settings.shake_intensity;
+    let t = follow.shake_time;
+    let shake_offset = Vec2::new(noise(t, 0.), noise(t, 17.)) * settings.max_shake_offset * shake;
+    let shake_angle = noise(t, 31.) * settings.max_shake_angle * shake;
+
+    let (mut transform, mut projection) = camera_query.single_mut();
+    let center = follow.position + shake_offset;
+    transform.translation = center.extend(transform.translation.z);
+    transform.rotation = Quat::from_rotation_z(shake_angle);
+    if projection.scale != follow.zoom {
+        projection.scale = follow.zoom;
+    }
+
+    view.center = center;
+    view.half_extents = window_half_extents * follow.zoom;
+    view.zoom = follow.zoom;
+}
+
+// Critically damped spring towards `target`, stable for any frame time
+fn smooth_damp(current: Vec2, target: Vec2, velocity: Vec2, omega: f32, delta: f32) -> (Vec2, Vec2) {
+    let x = omega * delta;
+    let decay = 1. / (1. + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (velocity + omega * change) * delta;
+    (target + (change + temp) * decay, (velocity - omega * temp) * decay)
+}
+
+// Cheap smooth noise in [-1, 1]; the seed just shifts the phase between axes
+fn noise(t: f32, seed: f32) -> f32 {
+    ((t * 23. + seed).sin() + (t * 37. + seed * 1.7).sin() * 0.5) / 1.5
+}
@@ -0,0 +1,235 @@
+use bevy::{prelude::*, ui::Val::Px};
+use heron::prelude::*;
+
+use crate::{
+    difficulty::Difficulty, is_pickup, is_player, net::offline, BoldFont, Chicken, Downed, Layer, PlayArea, Player,
+    PlayerDied, PlayerHealth, RestartRun, SizeScale, Speed, SubCenterText,
+};
+
+// Now and then `spawn_chasers` lets a chicken loose instead of a meteor. Chickens don't
+// hurt anyone and don't collide with meteors, though hazards get them like anything
+// else. They wander about until a player gets close, then run for it. Catching one
+// gives back a heart, and every catch is counted on the HUD and on the death screen.
+
+// Players closer than this, as a fraction of the play area's width, send chickens running
+const FLEE_DISTANCE: f32 = 0.3;
+// A bit slower than a player, so a chicken can always be caught in the end
+const CHICKEN_SPEED: f32 = 3.5;
+const CHICKEN_SCALE: f32 = 1.2;
+
+pub struct ChickenSettings {
+    // The chance of each spawn being a chicken instead of a meteor
+    pub spawn_chance: f64,
+}
+
+impl Default for ChickenSettings {
+    fn default() -> Self {
+        ChickenSettings { spawn_chance: 0.01 }
+    }
+}
+
+// Chickens caught this run
+#[derive(Default)]
+pub struct ChickensCaught(pub u32);
+
+// Sent whenever a player catches a chicken, for the sound and particles
+pub struct ChickenCaught {
+    pub position: Vec2,
+}
+
+pub struct ChickenPlugin;
+
+impl Plugin for ChickenPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ChickenSettings>()
+            .init_resource::<ChickensCaught>()
+            .add_event::<ChickenCaught>()
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_chicken_counter)
+            .add_system(reset_chickens)
+            .add_system(move_chickens.with_run_criteria(offline))
+            .add_system(catch_chickens.with_run_criteria(offline))
+            .add_system(update_chicken_counter)
+            .add_system(
+                show_chickens_on_death_screen
+                    .after("record_high_score")
+                    .after("announce_winner"),
+            );
+    }
+}
+
+pub fn spawn_chicken(commands: &mut Commands, texture: Handle<Image>, position: Vec2, window_width: f32) {
+    let size = window_width / 40. * CHICKEN_SCALE;
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture,
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(size, size)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(position.extend(0.0)),
+            ..Default::default()
+        })
+        .insert(Chicken)
+        .insert(Speed(CHICKEN_SPEED))
+        .insert(SizeScale(CHICKEN_SCALE))
+        .insert(RigidBody::Dynamic)
+        .insert(CollisionShape::Sphere { radius: size / 2. })
+        .insert(Velocity::default())
+        .insert(PhysicMaterial { friction: 1.0, density: 5.0, ..Default::default() })
+        .insert(Damping::from_linear(1.0))
+        .insert(RotationConstraints::lock())
+        .insert(CollisionLayers::new(Layer::Pickups, Layer::Player).with_mask(Layer::World));
+}
+
+// Chickens don't last from one run to the next, and neither does the count
+pub fn reset_chickens(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartRun>,
+    mut caught: ResMut<ChickensCaught>,
+    chicken_query: Query<Entity, With<Chicken>>,
+) {
+    if restart_events.iter().count() > 0 {
+        chicken_query.iter().for_each(|e| commands.entity(e).despawn());
+        caught.0 = 0;
+    }
+}
+
+pub fn move_chickens(
+    play_area: Res<PlayArea>,
+    player_died: Res<PlayerDied>,
+    mut chicken_query: Query<(&Transform, &Speed, &mut Velocity), With<Chicken>>,
+    player_query: Query<&Transform, (With<Player>, Without<Chicken>, Without<Downed>)>,
+) {
+    if player_died.0 {
+        return;
+    }
+
+    let flee_distance = play_area.0.x * FLEE_DISTANCE;
+    for (transform, Speed(speed), mut velocity) in chicken_query.iter_mut() {
+        let position = transform.translation.truncate();
+        let nearest = player_query.iter().map(|t| t.translation.truncate()).min_by(|a, b| {
+            a.distance_squared(position).partial_cmp(&b.distance_squared(position)).unwrap()
+        });
+
+        if let Some(player) = nearest {
+            if player.distance(position) < flee_distance {
+                let away = (position - player).normalize_or_zero();
+                velocity.linear += (away * *speed).extend(0.);
+            }
+        }
+    }
+}
+
+pub fn catch_chickens(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    difficulty: Res<Difficulty>,
+    mut caught: ResMut<ChickensCaught>,
+    mut caught_events: EventWriter<ChickenCaught>,
+    chicken_query: Query<&Transform, With<Chicken>>,
+    mut player_query: Query<&mut PlayerHealth>,
+) {
+    let max_health = difficulty.settings().hearts;
+    let mut despawned = Vec::new();
+
+    for event in events.iter().filter(|event| event.is_started()) {
+        let (layers_1, layers_2) = event.collision_layers();
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (player, chicken) = if is_player(layers_1) && is_pickup(layers_2) {
+            (entity_1, entity_2)
+        } else if is_player(layers_2) && is_pickup(layers_1) {
+            (entity_2, entity_1)
+        } else {
+            continue;
+        };
+
+        let transform = match chicken_query.get(chicken) {
+            Ok(transform) if !despawned.contains(&chicken) => transform,
+            _ => continue,
+        };
+
+        if let Ok(mut health) = player_query.get_mut(player) {
+            if health.0 > 0 && health.0 < max_health {
+                health.0 += 1;
+            }
+        }
+
+        commands.entity(chicken).despawn();
+        despawned.push(chicken);
+        caught.0 += 1;
+        caught_events.send(ChickenCaught {
+            position: transform.translation.truncate(),
+        });
+    }
+}
+
+#[derive(Component)]
+struct ChickenCounterText;
+
+// Down in the bottom left corner, out of the way of the hearts and the enemy count
+fn spawn_chicken_counter(mut commands: Commands, bold_font: Res<BoldFont>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Px(16.0),
+                    left: Px(24.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "Chickens Caught: ".to_string(),
+                        style: TextStyle {
+                            font: bold_font.0.clone(),
+                            font_size: 32.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "0".to_string(),
+                        style: TextStyle {
+                            font: bold_font.0.clone(),
+                            font_size: 32.0,
+                            color: Color::GOLD,
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ChickenCounterText);
+}
+
+fn update_chicken_counter(caught: Res<ChickensCaught>, mut text_query: Query<&mut Text, With<ChickenCounterText>>) {
+    if caught.is_changed() {
+        for mut text in text_query.iter_mut() {
+            text.sections[1].value = caught.0.to_string();
+        }
+    }
+}
+
+// Goes on top of whatever `record_high_score` or `announce_winner` wrote
+fn show_chickens_on_death_screen(
+    player_died: Res<PlayerDied>,
+    caught: Res<ChickensCaught>,
+    mut sub_center_text: Query<&mut Text, With<SubCenterText>>,
+) {
+    if !player_died.is_changed() || !player_died.0 || caught.0 == 0 {
+        return;
+    }
+
+    for mut text in sub_center_text.iter_mut() {
+        let chickens = match caught.0 {
+            1 => String::from("1 chicken caught"),
+            count => format!("{} chickens caught", count),
+        };
+        text.sections[0].value = format!("{}\n{}", chickens, text.sections[0].value);
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::VecDeque;
+
+use bevy::{input::InputSystem, prelude::*, ui::Val::Px, window::ReceivedCharacter};
+use heron::prelude::*;
+
+use crate::{
+    arena::{self, Arena, ArenaShape},
+    boss::{Boss, Bosses, SpawnBoss},
+    chickens::ChickenSettings,
+    hazards::Hazards,
+    command_line_value, difficulty::Difficulty, high_scores::SurvivalTime, menu::InMenu,
+    physics_debug::PhysicsDebugSettings, settings_menu::SettingsMenu, show_chaser_count, BoldFont, ChaserCount,
+    ChasingEnemy, Chicken, EnemyCountText, GamePaused, GodMode, NextRunSeed, Player, PlayerDied,
+    PlayerHealth, RestartRun, RunSeed, SpawnChasers, SpawnSizeIncrements, Speed,
+    PHYSICS_TIME_SCALE,
+};
+
+// Backtick opens a one line console for cheats and tuning. While it is open every key
+// is swallowed, the same way the settings menu does it. The same commands can be run
+// from a file with `--script <path>`, one per line, with `wait <seconds>` in between.
+
+const TOGGLE_KEY: KeyCode = KeyCode::Grave;
+const LOG_LINES: usize = 12;
+
+const HELP: &str = "god | spawn <n> [size] | clear | heal | set player_speed <v> | set chickens <chance> | timescale <x> | seed [n] | skip <seconds> | restart | colliders | boss [name] | boss skip | bosses on/off | hazards on/off | arena off/rectangle/circle [size]";
+
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    log: VecDeque<String>,
+}
+
+impl Console {
+    fn log(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        info!("console: {}", line);
+        self.log.push_back(line);
+        while self.log.len() > LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+}
+
+// A command typed into the console or read from the startup script
+pub struct ConsoleCommand(pub String);
+
+// Set by any command that changes a run in a way its replay can't reproduce. A run with
+// cheats stays off the score tables and isn't kept as a ghost or a replay.
+#[derive(Default)]
+pub struct CheatsUsed {
+    pub this_run: bool,
+    // `timescale` lasts until it's set back to 1
+    time_scaled: bool,
+}
+
+// Commands from `--script` that haven't run yet
+#[derive(Default)]
+struct ConsoleScript {
+    lines: VecDeque<String>,
+    wait: f32,
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Console>()
+            .init_resource::<CheatsUsed>()
+            .insert_resource(load_script())
+            .add_event::<ConsoleCommand>()
+            .add_system_to_stage(CoreStage::PreUpdate, console_input.label("console_input").after(InputSystem))
+            .add_system(run_console_script.label("run_console_script"))
+            .add_system(run_console_commands.label("run_console_commands").after("run_console_script"))
+            .add_system(reset_cheats_used.before("run_console_commands"))
+            .add_system(sync_console)
+            .add_system(update_console_text);
+    }
+}
+
+fn load_script() -> ConsoleScript {
+    let path = match command_line_value("--script") {
+        Some(path) => path,
+        None => return ConsoleScript::default(),
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => ConsoleScript {
+            lines: contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+            wait: 0.,
+        },
+        Err(e) => {
+            warn!("Could not read console script {}: {}", path, e);
+            ConsoleScript::default()
+        }
+    }
+}
+
+fn run_console_script(
+    time: Res<Time>,
+    mut script: ResMut<ConsoleScript>,
+    mut commands: EventWriter<ConsoleCommand>,
+) {
+    if script.lines.is_empty() {
+        return;
+    }
+
+    script.wait -= time.delta_seconds();
+    while script.wait <= 0. {
+        let line = match script.lines.pop_front() {
+            Some(line) => line,
+            None => break,
+        };
+
+        // `wait` only makes sense in a script, so it is handled here instead of with the other commands
+        if let Some(seconds) = line.strip_prefix("wait ") {
+            match seconds.trim().parse::<f32>() {
+                Ok(seconds) => script.wait = seconds,
+                Err(_) => warn!("console script: bad wait \"{}\"", line),
+            }
+        } else {
+            commands.send(ConsoleCommand(line));
+        }
+    }
+}
+
+fn console_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut console: ResMut<Console>,
+    mut commands: EventWriter<ConsoleCommand>,
+    settings_menu: Res<SettingsMenu>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_KEY) && !settings_menu.open {
+        console.open = !console.open;
+        keyboard_input.reset(TOGGLE_KEY);
+        characters.iter().for_each(drop);
+        return;
+    }
+
+    if !console.open {
+        characters.iter().for_each(drop);
+        return;
+    }
+
+    for character in characters.iter() {
+        if !character.char.is_control() && character.char != '`' {
+            console.input.push(character.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        if !line.trim().is_empty() {
+            console.log(format!("> {}", line));
+            commands.send(ConsoleCommand(line));
+        }
+    }
+
+    let pressed: Vec<KeyCode> = keyboard_input.get_just_pressed().copied().collect();
+    for key in pressed {
+        keyboard_input.reset(key);
+    }
+}
+
+// God mode and the time scale carry over into the next run, so that run starts out cheated too
+fn reset_cheats_used(
+    mut restart_events: EventReader<RestartRun>,
+    god_mode: Res<GodMode>,
+    mut cheats_used: ResMut<CheatsUsed>,
+) {
+    if restart_events.iter().count() > 0 {
+        cheats_used.this_run = god_mode.0 || cheats_used.time_scaled;
+    }
+}
+
+fn run_console_commands(
+    mut commands: Commands,
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    (mut god_mode, mut physics_debug): (ResMut<GodMode>, ResMut<PhysicsDebugSettings>),
+    mut spawn_events: EventWriter<SpawnChasers>,
+    mut restart_events: EventWriter<RestartRun>,
+    (mut chaser_count, mut size_increments, mut survival_time): (ResMut<ChaserCount>, ResMut<SpawnSizeIncrements>, ResMut<SurvivalTime>),
+    (mut physics_time, game_paused): (ResMut<PhysicsTime>, Res<GamePaused>),
+    (run_seed, mut next_seed, mut in_menu): (Res<RunSeed>, ResMut<NextRunSeed>, ResMut<InMenu>),
+    (difficulty, player_died, mut chicken_settings): (Res<Difficulty>, Res<PlayerDied>, ResMut<ChickenSettings>),
+    (mut bosses, mut boss_events, boss_query): (ResMut<Bosses>, EventWriter<SpawnBoss>, Query<Entity, With<Boss>>),
+    (mut hazards, mut arena, mut cheats_used): (ResMut<Hazards>, ResMut<Arena>, ResMut<CheatsUsed>),
+    mut player_query: Query<(&mut PlayerHealth, &mut Speed), With<Player>>,
+    chaser_query: Query<Entity, Or<(With<ChasingEnemy>, With<Chicken>)>>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+) {
+    // Despawns don't happen until the end of the frame, so `clear` then `boss skip` in
+    // the same frame would otherwise see the bosses again and count them out twice
+    let mut despawned: Vec<Entity> = Vec::new();
+    for ConsoleCommand(line) in console_commands.iter() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| words.get(index).and_then(|word| word.parse::<f32>().ok());
+        // Run settings are recorded when a run starts, so changing them partway through is a cheat too
+        let mid_run = !in_menu.0;
+
+        match words.as_slice() {
+            ["help"] => console.log(HELP),
+            ["god"] => {
+                god_mode.0 = !god_mode.0;
+                cheats_used.this_run = true;
+                console.log(format!("god mode {}", if god_mode.0 { "on" } else { "off" }));
+            }
+            ["spawn", ..] => match number(1) {
+                Some(count) if count >= 1. => {
+                    spawn_events.send(SpawnChasers {
+                        count: count as u32,
+                        size_scale: number(2),
+                    });
+                    cheats_used.this_run = true;
+                    console.log(format!("spawning {} chasers", count as u32));
+                }
+                _ => console.log("usage: spawn <n> [size]"),
+            },
+            ["clear"] => {
+                // Bosses are chasers too, so they go and the count drops to nothing. Chickens
+                // don't count, but go as well so the screen really is empty.
+                for e in chaser_query.iter().filter(|e| !despawned.contains(e)) {
+                    commands.entity(e).despawn();
+                    despawned.push(e);
+                }
+                chaser_count.current = 0;
+                cheats_used.this_run = true;
+                show_chaser_count(&chaser_count, enemy_count_text_query.iter_mut());
+                console.log("cleared all chasers and chickens");
+            }
+            ["heal"] if player_died.0 => console.log("can't heal after dying, use restart"),
+            ["heal"] => {
+                for (mut health, _) in player_query.iter_mut() {
+                    health.0 = difficulty.settings().hearts;
+                }
+                cheats_used.this_run = true;
+                console.log("healed");
+            }
+            ["set", "player_speed", _] => match number(2) {
+                Some(speed) => {
+                    for (_, mut player_speed) in player_query.iter_mut() {
+                        player_speed.0 = speed;
+                    }
+                    cheats_used.this_run = true;
+                    console.log(format!("player speed set to {}", speed));
+                }
+                None => console.log("usage: set player_speed <v>"),
+            },
+            ["set", "chickens", _] => match number(2) {
+                Some(chance) if (0. ..=1.).contains(&chance) => {
+                    chicken_settings.spawn_chance = chance as f64;
+                    cheats_used.this_run |= mid_run;
+                    console.log(format!("chicken chance set to {}", chance));
+                }
+                _ => console.log("usage: set chickens <chance from 0 to 1>"),
+            },
+            ["timescale", _] => match number(1) {
+                Some(scale) if scale > 0. => {
+                    // Scaling a paused `PhysicsTime` would unpause it
+                    if game_paused.0 {
+                        physics_time.resume();
+                        physics_time.set_scale(PHYSICS_TIME_SCALE * scale);
+                        physics_time.pause();
+                    } else {
+                        physics_time.set_scale(PHYSICS_TIME_SCALE * scale);
+                    }
+                    cheats_used.this_run = true;
+                    cheats_used.time_scaled = scale != 1.;
+                    console.log(format!("physics time scale set to {}x", scale));
+                }
+                _ => console.log("usage: timescale <x>"),
+            },
+            ["seed"] => console.log(format!("seed {}", run_seed.0)),
+            ["seed", seed] => match seed.parse::<u64>() {
+                Ok(seed) => {
+                    next_seed.0 = Some(seed);
+                    in_menu.0 = false;
+                    restart_events.send(RestartRun);
+                    console.log(format!("restarting with seed {}", seed));
+                }
+                Err(_) => console.log("usage: seed [n]"),
+            },
+            ["colliders"] => {
+                physics_debug.enabled = !physics_debug.enabled;
+                console.log(format!("collider drawing {}", if physics_debug.enabled { "on" } else { "off" }));
+            }
+            ["restart"] => {
+                in_menu.0 = false;
+                restart_events.send(RestartRun);
+                console.log("restarting");
+            }
+            // Fast forwards the difficulty curve rather than the simulation
+            ["skip", _] => match number(1) {
+                Some(seconds) if seconds > 0. => {
+                    let settings = difficulty.settings();
+                    let increments = size_increments.0 as f32 + seconds / settings.size_increase_interval;
+                    size_increments.0 = increments.min(100.) as u8;
+                    survival_time.0 += seconds;
+                    spawn_events.send(SpawnChasers {
+                        count: (seconds / settings.spawn_interval) as u32,
+                        size_scale: None,
+                    });
+                    cheats_used.this_run = true;
+                    console.log(format!("skipped {} seconds", seconds));
+                }
+                _ => console.log("usage: skip <seconds>"),
+            },
+            ["boss", "skip"] => {
+                for boss in boss_query.iter().filter(|e| !despawned.contains(e)) {
+                    commands.entity(boss).despawn();
+                    despawned.push(boss);
+                    chaser_count.current = chaser_count.current.saturating_sub(1);
+                }
+                cheats_used.this_run = true;
+                console.log("skipped the boss");
+            }
+            ["boss", name @ ..] => {
+                let name = name.join(" ");
+                if name.is_empty() {
+                    boss_events.send(SpawnBoss(None));
+                    cheats_used.this_run = true;
+                    console.log("bringing in the next boss");
+                } else if bosses.definitions.iter().any(|d| d.name.eq_ignore_ascii_case(&name)) {
+                    console.log(format!("bringing in {}", name));
+                    boss_events.send(SpawnBoss(Some(name)));
+                    cheats_used.this_run = true;
+                } else {
+                    let names: Vec<&str> = bosses.definitions.iter().map(|d| d.name.as_str()).collect();
+                    console.log(format!("no boss called \"{}\", try one of {}", name, names.join(", ")));
+                }
+            }
+            ["bosses", "on"] | ["bosses", "off"] => {
+                bosses.enabled = words[1] == "on";
+                cheats_used.this_run |= mid_run;
+                console.log(format!("bosses {}", words[1]));
+            }
+            ["hazards", "on"] | ["hazards", "off"] => {
+                hazards.enabled = words[1] == "on";
+                cheats_used.this_run |= mid_run;
+                console.log(format!("hazards {}", words[1]));
+            }
+            ["arena", "off"] => {
+                arena.shape = None;
+                cheats_used.this_run |= mid_run;
+                console.log("arena off");
+            }
+            ["arena", shape, ..] if *shape == "rectangle" || *shape == "circle" => {
+                let size = match words.get(2) {
+                    None => arena.size,
+                    Some(_) => match number(2) {
+                        Some(size) if (arena::MIN_SIZE..=arena::MAX_SIZE).contains(&size) => size,
+                        _ => {
+                            console.log(format!("arena size goes from {} to {} screens", arena::MIN_SIZE, arena::MAX_SIZE));
+                            continue;
+                        }
+                    },
+                };
+                arena.shape = Some(if *shape == "circle" { ArenaShape::Circle } else { ArenaShape::Rectangle });
+                arena.size = size;
+                cheats_used.this_run |= mid_run;
+                console.log(format!("{} arena {} screens across", shape, size));
+            }
+            _ => console.log(format!("unknown command \"{}\", try help", line.trim())),
+        }
+    }
+}
+
+#[derive(Component)]
+struct ConsoleRoot;
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn sync_console(
+    mut commands: Commands,
+    console: Res<Console>,
+    bold_font: Res<BoldFont>,
+    root_query: Query<Entity, With<ConsoleRoot>>,
+) {
+    let spawned = root_query.iter().next().is_some();
+
+    if console.open && !spawned {
+        spawn_console(&mut commands, bold_font.0.clone());
+    } else if !console.open && spawned {
+        root_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    }
+}
+
+fn spawn_console(commands: &mut Commands, font: Handle<Font>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Px(0.0),
+                    left: Px(0.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Px(8.0)),
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            ..Default::default()
+        })
+        .insert(ConsoleRoot)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font,
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ConsoleText);
+        });
+}
+
+fn update_console_text(console: Res<Console>, mut text_query: Query<&mut Text, With<ConsoleText>>) {
+    if !console.open {
+        return;
+    }
+
+    for mut text in text_query.iter_mut() {
+        let mut value = String::new();
+        for line in console.log.iter() {
+            value.push_str(line);
+            value.push('\n');
+        }
+        value.push_str(&format!("> {}_", console.input));
+        text.sections[0].value = value;
+    }
+}
@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Everything the player can do with the keyboard during a run. Menus keep their own fixed keys.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InputAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Pause,
+    Restart,
+    ToggleRadar,
+    Fullscreen,
+    Repulse,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 9] = [
+        InputAction::MoveUp,
+        InputAction::MoveDown,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Pause,
+        InputAction::Restart,
+        InputAction::ToggleRadar,
+        InputAction::Fullscreen,
+        InputAction::Repulse,
+    ];
+
+    // What each player has their own keys for when two share the keyboard
+    pub const PER_PLAYER: [InputAction; 5] = [
+        InputAction::MoveUp,
+        InputAction::MoveDown,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Repulse,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputAction::MoveUp => "Move Up",
+            InputAction::MoveDown => "Move Down",
+            InputAction::MoveLeft => "Move Left",
+            InputAction::MoveRight => "Move Right",
+            InputAction::Pause => "Pause",
+            InputAction::Restart => "Restart",
+            InputAction::ToggleRadar => "Toggle Radar",
+            InputAction::Fullscreen => "Fullscreen",
+            InputAction::Repulse => "Repulse (Versus)",
+        }
+    }
+}
+
+// Rebinding only ever replaces the primary key, so the secondary keys
+// (arrows, Escape) always work as a fallback
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct KeyBinding {
+    pub primary: KeyCode,
+    pub secondary: Option<KeyCode>,
+}
+
+impl KeyBinding {
+    fn new(primary: KeyCode, secondary: Option<KeyCode>) -> Self {
+        KeyBinding { primary, secondary }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub move_up: KeyBinding,
+    pub move_down: KeyBinding,
+    pub move_left: KeyBinding,
+    pub move_right: KeyBinding,
+    pub pause: KeyBinding,
+    pub restart: KeyBinding,
+    pub toggle_radar: KeyBinding,
+    pub fullscreen: KeyBinding,
+    pub repulse: KeyBinding,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            move_up: KeyBinding::new(KeyCode::W, Some(KeyCode::Up)),
+            move_down: KeyBinding::new(KeyCode::S, Some(KeyCode::Down)),
+            move_left: KeyBinding::new(KeyCode::A, Some(KeyCode::Left)),
+            move_right: KeyBinding::new(KeyCode::D, Some(KeyCode::Right)),
+            pause: KeyBinding::new(KeyCode::Space, Some(KeyCode::Escape)),
+            restart: KeyBinding::new(KeyCode::R, None),
+            toggle_radar: KeyBinding::new(KeyCode::Tab, None),
+            fullscreen: KeyBinding::new(KeyCode::F11, None),
+            repulse: KeyBinding::new(KeyCode::LShift, Some(KeyCode::RShift)),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: InputAction) -> &KeyBinding {
+        match action {
+            InputAction::MoveUp => &self.move_up,
+            InputAction::MoveDown => &self.move_down,
+            InputAction::MoveLeft => &self.move_left,
+            InputAction::MoveRight => &self.move_right,
+            InputAction::Pause => &self.pause,
+            InputAction::Restart => &self.restart,
+            InputAction::ToggleRadar => &self.toggle_radar,
+            InputAction::Fullscreen => &self.fullscreen,
+            InputAction::Repulse => &self.repulse,
+        }
+    }
+
+    pub fn get_mut(&mut self, action: InputAction) -> &mut KeyBinding {
+        match action {
+            InputAction::MoveUp => &mut self.move_up,
+            InputAction::MoveDown => &mut self.move_down,
+            InputAction::MoveLeft => &mut self.move_left,
+            InputAction::MoveRight => &mut self.move_right,
+            InputAction::Pause => &mut self.pause,
+            InputAction::Restart => &mut self.restart,
+            InputAction::ToggleRadar => &mut self.toggle_radar,
+            InputAction::Fullscreen => &mut self.fullscreen,
+            InputAction::Repulse => &mut self.repulse,
+        }
+    }
+
+    pub fn pressed(&self, action: InputAction, input: &Input<KeyCode>) -> bool {
+        let binding = self.get(action);
+        input.pressed(binding.primary) || binding.secondary.map_or(false, |key| input.pressed(key))
+    }
+
+    pub fn just_pressed(&self, action: InputAction, input: &Input<KeyCode>) -> bool {
+        let binding = self.get(action);
+        input.just_pressed(binding.primary) || binding.secondary.map_or(false, |key| input.just_pressed(key))
+    }
+}
+
+// The actions held down during one frame, one bit per `InputAction::ALL` entry.
+// Small enough to store for every frame of a replay.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ActionSet(pub u16);
+
+impl ActionSet {
+    fn bit(action: InputAction) -> u16 {
+        let index = InputAction::ALL.iter().position(|a| *a == action).unwrap();
+        1 << index
+    }
+
+    pub fn insert(&mut self, action: InputAction) {
+        self.0 |= Self::bit(action);
+    }
+
+    pub fn contains(&self, action: InputAction) -> bool {
+        self.0 & Self::bit(action) != 0
+    }
+
+    pub fn pressed(bindings: &KeyBindings, input: &Input<KeyCode>) -> Self {
+        let mut actions = ActionSet::default();
+        for action in InputAction::ALL.iter() {
+            if bindings.pressed(*action, input) {
+                actions.insert(*action);
+            }
+        }
+        actions
+    }
+
+    // `PER_PLAYER` actions only, from either the primary or the secondary keys. Used to
+    // split the keyboard between two players.
+    pub fn pressed_half(bindings: &KeyBindings, input: &Input<KeyCode>, secondary: bool) -> Self {
+        let mut actions = ActionSet::default();
+        for action in InputAction::PER_PLAYER.iter() {
+            let binding = bindings.get(*action);
+            let key = if secondary { binding.secondary } else { Some(binding.primary) };
+            if key.map_or(false, |key| input.pressed(key)) {
+                actions.insert(*action);
+            }
+        }
+        actions
+    }
+
+    // Both the D-pad and the left stick move, the stick only once it's pushed past `threshold`.
+    // The bottom face button repulses.
+    pub fn add_gamepad(
+        &mut self,
+        gamepad: Gamepad,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+        threshold: f32,
+    ) {
+        let stick_x = axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.);
+        let stick_y = axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.);
+        let button = |button_type| buttons.pressed(GamepadButton(gamepad, button_type));
+
+        if stick_y > threshold || button(GamepadButtonType::DPadUp) {
+            self.insert(InputAction::MoveUp);
+        }
+        if stick_y < -threshold || button(GamepadButtonType::DPadDown) {
+            self.insert(InputAction::MoveDown);
+        }
+        if stick_x < -threshold || button(GamepadButtonType::DPadLeft) {
+            self.insert(InputAction::MoveLeft);
+        }
+        if stick_x > threshold || button(GamepadButtonType::DPadRight) {
+            self.insert(InputAction::MoveRight);
+        }
+        if button(GamepadButtonType::South) {
+            self.insert(InputAction::Repulse);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+
+use crate::controls::{ActionSet, KeyBindings};
+
+// Local co-op puts a second Earth on the same screen. The keyboard is split in two,
+// player one keeps the primary movement keys and player two gets the secondary ones
+// (the arrows), and each connected gamepad drives the player with the same number.
+// Meteors go after whichever living player is closest, and the run only ends once
+// every player is out of hearts.
+
+// Local co-op only uses the first two, a network game can fill all of them
+pub const MAX_PLAYERS: usize = 4;
+
+// Every player after the first is tinted so the Earths can be told apart, and so are their hearts
+pub const PLAYER_COLORS: [Color; MAX_PLAYERS] = [
+    Color::WHITE,
+    Color::rgb(1.0, 0.7, 0.45),
+    Color::rgb(0.55, 1.0, 0.6),
+    Color::rgb(0.85, 0.6, 1.0),
+];
+
+// How far a stick has to be pushed before it counts as a direction
+const STICK_THRESHOLD: f32 = 0.5;
+
+// Picked in the menu. Only read when a run starts, see `reset_players`.
+pub struct PlayerCount(pub usize);
+
+impl Default for PlayerCount {
+    fn default() -> Self {
+        PlayerCount(1)
+    }
+}
+
+// In the order they were plugged in, so the first gamepad is always player one's
+#[derive(Default)]
+pub struct ConnectedGamepads(Vec<Gamepad>);
+
+pub struct CoopPlugin;
+
+impl Plugin for CoopPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<PlayerCount>()
+            .init_resource::<ConnectedGamepads>()
+            .add_system_to_stage(CoreStage::PreUpdate, track_gamepads.label("track_gamepads"));
+    }
+}
+
+fn track_gamepads(mut gamepad_events: EventReader<GamepadEvent>, mut gamepads: ResMut<ConnectedGamepads>) {
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                if !gamepads.0.contains(gamepad) {
+                    gamepads.0.push(*gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => gamepads.0.retain(|g| g != gamepad),
+            _ => {}
+        }
+    }
+}
+
+// What the player in `slot` is holding down this frame
+pub fn player_actions(
+    slot: usize,
+    player_count: usize,
+    bindings: &KeyBindings,
+    keyboard_input: &Input<KeyCode>,
+    gamepads: &ConnectedGamepads,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+) -> ActionSet {
+    let mut actions = if player_count > 1 {
+        ActionSet::pressed_half(bindings, keyboard_input, slot > 0)
+    } else {
+        ActionSet::pressed(bindings, keyboard_input)
+    };
+
+    if let Some(gamepad) = gamepads.0.get(slot) {
+        actions.add_gamepad(*gamepad, buttons, axes, STICK_THRESHOLD);
+    }
+
+    actions
+}
+
+// The middle of the given players and how far they spread out from it on each axis
+pub fn player_bounds(positions: impl Iterator<Item = Vec2>) -> Option<(Vec2, Vec2)> {
+    let (min, max) = positions.fold(None, |bounds: Option<(Vec2, Vec2)>, position| match bounds {
+        Some((min, max)) => Some((min.min(position), max.max(position))),
+        None => Some((position, position)),
+    })?;
+    Some(((min + max) / 2., (max - min) / 2.))
+}
+
+// Players start side by side, a little apart
+pub fn start_position(slot: usize, player_count: usize, window_width: f32) -> Vec3 {
+    let spacing = window_width / 8.;
+    let x = (slot as f32 - (player_count as f32 - 1.) / 2.) * spacing;
+    Vec3::new(x, 0.0, 0.0)
+}
@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{date::Date, menu::InMenu, starfield::mix, storage};
+
+// The daily challenge seeds every run from the UTC date, so everyone gets the same
+// spawn sequence on the same day without needing a server. It is always played on
+// Normal and keeps its own table, which starts over when the day changes.
+
+const DAILY_KEY: &str = "daily";
+const MAX_DAILY_ENTRIES: usize = 5;
+
+// Set when a daily challenge is started from the menu, and every restart from the death
+// screen replays the same day. Cleared when going back to the menu.
+#[derive(Default)]
+pub struct DailyChallenge {
+    pub date: Option<Date>,
+}
+
+impl DailyChallenge {
+    pub fn seed(&self) -> Option<u64> {
+        self.date.map(daily_seed)
+    }
+}
+
+pub fn daily_seed(date: Date) -> u64 {
+    mix(date.unix_days() as u64 ^ 0xDA11_5EED)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct DailyEntry {
+    pub seconds: f32,
+    pub chasers: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct DailyScores {
+    date: Option<Date>,
+    entries: Vec<DailyEntry>,
+}
+
+impl DailyScores {
+    fn load() -> Self {
+        storage::load(DAILY_KEY)
+            .and_then(|contents| ron::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        match ron::to_string(self) {
+            Ok(contents) => storage::save(DAILY_KEY, &contents),
+            Err(e) => warn!("Could not serialize daily scores: {}", e),
+        }
+    }
+
+    pub fn table(&self, date: Date) -> &[DailyEntry] {
+        if self.date == Some(date) {
+            &self.entries
+        } else {
+            &[]
+        }
+    }
+
+    pub fn best(&self, date: Date) -> Option<DailyEntry> {
+        self.table(date).first().copied()
+    }
+
+    // Returns the placement of the new entry, if it made it onto the table
+    pub fn submit(&mut self, date: Date, entry: DailyEntry) -> Option<usize> {
+        let placement = self.insert(date, entry);
+        if placement.is_some() {
+            self.save();
+        }
+        placement
+    }
+
+    fn insert(&mut self, date: Date, entry: DailyEntry) -> Option<usize> {
+        if self.date != Some(date) {
+            self.date = Some(date);
+            self.entries.clear();
+        }
+
+        let position = self
+            .entries
+            .iter()
+            .position(|e| entry.seconds > e.seconds)
+            .unwrap_or(self.entries.len());
+
+        if position >= MAX_DAILY_ENTRIES {
+            return None;
+        }
+
+        self.entries.insert(position, entry);
+        self.entries.truncate(MAX_DAILY_ENTRIES);
+        Some(position)
+    }
+}
+
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<DailyChallenge>()
+            .insert_resource(DailyScores::load())
+            .add_system(leave_daily_in_menu);
+    }
+}
+
+fn leave_daily_in_menu(in_menu: Res<InMenu>, mut daily: ResMut<DailyChallenge>) {
+    if in_menu.0 && daily.date.is_some() {
+        daily.date = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(seconds: f32) -> DailyEntry {
+        DailyEntry { seconds, chasers: 0 }
+    }
+
+    // `insert` rather than `submit`, which would also write the table to disk
+    #[test]
+    fn keeps_the_best_five_in_order() {
+        let today = Date { year: 2026, month: 10, day: 18 };
+        let mut scores = DailyScores::default();
+        for seconds in [30., 10., 50., 20., 40.] {
+            assert!(scores.insert(today, entry(seconds)).is_some());
+        }
+        assert_eq!(scores.insert(today, entry(5.)), None);
+        assert_eq!(scores.insert(today, entry(45.)), Some(1));
+
+        let table: Vec<f32> = scores.table(today).iter().map(|e| e.seconds).collect();
+        assert_eq!(table, vec![50., 45., 40., 30., 20.]);
+    }
+
+    #[test]
+    fn starts_over_on_a_new_day() {
+        let today = Date { year: 2026, month: 10, day: 18 };
+        let tomorrow = Date::from_unix_days(today.unix_days() + 1);
+        let mut scores = DailyScores::default();
+        scores.insert(today, entry(100.));
+
+        assert_eq!(scores.insert(tomorrow, entry(1.)), Some(0));
+        assert!(scores.table(today).is_empty());
+        assert_eq!(scores.best(tomorrow).map(|e| e.seconds), Some(1.));
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// Just enough calendar for naming replays and daily challenges. Always UTC, so
+// everyone agrees on what day it is no matter where they play.
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    // Howard Hinnant's days to civil date conversion
+    pub fn from_unix_days(days: i64) -> Date {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+
+        Date {
+            year: year_of_era + era * 400 + if month <= 2 { 1 } else { 0 },
+            month: month as u32,
+            day: day as u32,
+        }
+    }
+
+    pub fn today() -> Date {
+        Date::from_unix_days((unix_time() / 86400) as i64)
+    }
+
+    // Days since 1970-01-01, the inverse of `from_unix_days`
+    pub fn unix_days(&self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = self.month as i64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+// "2026-10-18 14:03"
+pub fn format_timestamp(unix_seconds: u64) -> String {
+    let seconds_of_day = unix_seconds % 86400;
+    format!(
+        "{} {:02}:{:02}",
+        Date::from_unix_days((unix_seconds / 86400) as i64),
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+    )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// `SystemTime` isn't available in the browser, but the JS clock is
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time() -> u64 {
+    (js_sys::Date::now() / 1000.) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i64, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(Date::from_unix_days(0), date(1970, 1, 1));
+        assert_eq!(Date::from_unix_days(-1), date(1969, 12, 31));
+        assert_eq!(date(1970, 1, 1).unix_days(), 0);
+    }
+
+    #[test]
+    fn month_and_year_ends() {
+        assert_eq!(Date::from_unix_days(19722), date(2023, 12, 31));
+        assert_eq!(Date::from_unix_days(19723), date(2024, 1, 1));
+        assert_eq!(date(2026, 10, 18).unix_days(), 20744);
+    }
+
+    #[test]
+    fn leap_years() {
+        // 2000 and 2024 have a February 29th, 1900 doesn't
+        assert_eq!(Date::from_unix_days(11016), date(2000, 2, 29));
+        assert_eq!(Date::from_unix_days(11017), date(2000, 3, 1));
+        assert_eq!(Date::from_unix_days(19782), date(2024, 2, 29));
+        assert_eq!(Date::from_unix_days(-25509), date(1900, 2, 28));
+        assert_eq!(Date::from_unix_days(-25508), date(1900, 3, 1));
+    }
+
+    #[test]
+    fn round_trips() {
+        for days in (-800_000..800_000).step_by(97) {
+            assert_eq!(Date::from_unix_days(days).unix_days(), days);
+        }
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(date(2026, 3, 7).to_string(), "2026-03-07");
+        assert_eq!(format_timestamp(86400 + 3600 * 13 + 60 * 5 + 59), "1970-01-02 13:05");
+    }
+}
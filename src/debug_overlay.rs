@@ -0,0 +1,110 @@
+use bevy::{
+    diagnostic::{DiagnosticId, Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    ui::Val::Px,
+};
+use heron::prelude::*;
+
+use crate::{ChaserCount, SpawnSizeIncrements};
+
+// Only compiled in with the `debug_overlay` feature, e.g.
+// `cargo run --features debug_overlay`, so release builds never carry it.
+// The player facing FPS counter in the settings is separate and always available.
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugin(EntityCountDiagnosticsPlugin::default())
+            .insert_resource(DebugOverlayRefreshTimer(Timer::from_seconds(0.25, true)))
+            .add_startup_system(spawn_debug_overlay)
+            .add_system(toggle_debug_overlay)
+            .add_system(update_debug_overlay);
+    }
+}
+
+#[derive(Component)]
+struct DebugOverlayText;
+
+// The numbers are unreadable if they change every frame
+struct DebugOverlayRefreshTimer(Timer);
+
+fn spawn_debug_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Px(90.0),
+                    right: Px(16.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/Fredoka/Fredoka-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::YELLOW_GREEN,
+                },
+                Default::default(),
+            ),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(DebugOverlayText);
+}
+
+fn toggle_debug_overlay(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut Visibility, With<DebugOverlayText>>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_KEY) {
+        for mut visibility in query.iter_mut() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+fn update_debug_overlay(
+    diagnostics: Res<Diagnostics>,
+    time: Res<Time>,
+    mut timer: ResMut<DebugOverlayRefreshTimer>,
+    chaser_count: Res<ChaserCount>,
+    size_increments: Res<SpawnSizeIncrements>,
+    body_query: Query<(), With<RigidBody>>,
+    mut text_query: Query<(&mut Text, &Visibility), With<DebugOverlayText>>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let average = |id: DiagnosticId| diagnostics.get(id).and_then(|d| d.average()).unwrap_or(0.);
+    let fps = average(FrameTimeDiagnosticsPlugin::FPS);
+    let frame_time = average(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.;
+    let entities = diagnostics
+        .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .and_then(|d| d.value())
+        .unwrap_or(0.);
+
+    for (mut text, visibility) in text_query.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        text.sections[0].value = format!(
+            "FPS: {:.1}\nFrame time: {:.2} ms\nEntities: {}\nChasers: {} / {}\nPhysics bodies: {}\nSpawn size increments: {}",
+            fps,
+            frame_time,
+            entities as u32,
+            chaser_count.current,
+            chaser_count.max,
+            body_query.iter().count(),
+            size_increments.0,
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// The difficulty is stored directly as a resource and picked in the menu before each run
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+// All of the balance values that change between difficulties
+pub struct DifficultySettings {
+    pub spawn_interval: f32,
+    pub chaser_speed: f32,
+    pub hearts: u8,
+    pub size_increase_interval: f32,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    pub fn settings(&self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                spawn_interval: 0.8,
+                chaser_speed: 2.0,
+                hearts: 7,
+                size_increase_interval: 8.0,
+            },
+            Difficulty::Normal => DifficultySettings {
+                spawn_interval: 0.5,
+                chaser_speed: 2.5,
+                hearts: 5,
+                size_increase_interval: 5.0,
+            },
+            Difficulty::Hard => DifficultySettings {
+                spawn_interval: 0.35,
+                chaser_speed: 3.0,
+                hearts: 4,
+                size_increase_interval: 3.5,
+            },
+            Difficulty::Insane => DifficultySettings {
+                spawn_interval: 0.2,
+                chaser_speed: 3.75,
+                hearts: 3,
+                size_increase_interval: 2.0,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Insane => "Insane",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Difficulty::Easy => Color::GREEN,
+            Difficulty::Normal => Color::WHITE,
+            Difficulty::Hard => Color::ORANGE,
+            Difficulty::Insane => Color::RED,
+        }
+    }
+
+    pub fn next(&self) -> Difficulty {
+        let index = Difficulty::ALL.iter().position(|d| d == self).unwrap();
+        Difficulty::ALL[(index + 1) % Difficulty::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Difficulty {
+        let index = Difficulty::ALL.iter().position(|d| d == self).unwrap();
+        Difficulty::ALL[(index + Difficulty::ALL.len() - 1) % Difficulty::ALL.len()]
+    }
+}
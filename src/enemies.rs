@@ -0,0 +1,232 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::starfield::mix;
+
+// The kinds of meteor `spawn_chasers` picks from live in `assets/data/meteors.enemies.ron`,
+// so a new one only needs a new entry there (and a sprite, if it wants its own). The
+// file is read through the asset server like everything else in `assets`, which also
+// means editing it while the game runs takes effect on the next spawn when the asset
+// server is watching for changes. Replays keep `EnemyRegistry::hash` so one recorded
+// with different enemies isn't played back as if nothing had changed.
+//
+// Chickens aren't in here, they are pickups rather than enemies, see `chickens`.
+
+const REGISTRY_PATH: &str = "data/meteors.enemies.ron";
+// A drifter's speed in units per second for every unit of chaser speed it has
+pub const DRIFT_SPEED: f32 = 160.;
+
+#[derive(Deserialize, Clone)]
+pub struct EnemyType {
+    pub name: String,
+    pub sprite: String,
+    // Tints the sprite, so one image can be used for several kinds
+    #[serde(default = "white")]
+    pub color: (f32, f32, f32),
+    // The range `SizeScale` is picked from
+    pub size: (f32, f32),
+    // Added to the top of `size` for every `SpawnSizeIncrements`, so the kind grows over a run
+    #[serde(default)]
+    pub size_growth: f32,
+    // Multiplies the difficulty's chaser speed
+    pub speed: f32,
+    // Multiplied by `SizeScale`, so bigger meteors of the same kind are also denser
+    pub density: f32,
+    // Hearts lost on contact
+    pub damage: u8,
+    pub behavior: EnemyBehavior,
+    // How likely this kind is compared to the others
+    pub weight: u32,
+}
+
+fn white() -> (f32, f32, f32) {
+    (1.0, 1.0, 1.0)
+}
+
+#[derive(Component, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnemyBehavior {
+    // Steers towards the nearest player the whole time, see `move_chasing_enemies`
+    Chase,
+    // Flies in a straight line at wherever the players were when it spawned
+    Drift,
+}
+
+#[derive(Component)]
+pub struct Damage(pub u8);
+
+#[derive(TypeUuid)]
+#[uuid = "6b1c6a1e-3f0e-4a8e-9d4b-2f57c1a0e9d3"]
+pub struct EnemyRegistry {
+    pub types: Vec<EnemyType>,
+    // Of the file as loaded, see `replay`
+    pub hash: u64,
+}
+
+impl EnemyRegistry {
+    // `roll` is anywhere from 0 up to, but not including, `total_weight`
+    pub fn pick(&self, roll: u32) -> Option<(usize, &EnemyType)> {
+        let mut remaining = roll;
+        for (index, enemy_type) in self.types.iter().enumerate() {
+            if remaining < enemy_type.weight {
+                return Some((index, enemy_type));
+            }
+            remaining -= enemy_type.weight;
+        }
+        None
+    }
+
+    pub fn total_weight(&self) -> u32 {
+        self.types.iter().map(|t| t.weight).sum()
+    }
+}
+
+#[derive(Default)]
+struct EnemyRegistryLoader;
+
+impl AssetLoader for EnemyRegistryLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let types: Vec<EnemyType> = ron::de::from_bytes(bytes)?;
+            let hash = bytes.iter().fold(0, |hash, byte| mix(hash ^ *byte as u64));
+            load_context.set_default_asset(LoadedAsset::new(EnemyRegistry { types, hash }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["enemies.ron"]
+    }
+}
+
+// `sprites` lines up with `EnemyRegistry::types`. It stays empty on the headless
+// server, which has nothing to draw them with.
+pub struct Enemies {
+    pub registry: Handle<EnemyRegistry>,
+    pub sprites: Vec<Handle<Image>>,
+}
+
+impl Enemies {
+    // Runs don't start until this is there, see `replay::advance_tick`
+    pub fn loaded<'a>(&self, registries: &'a Assets<EnemyRegistry>) -> Option<&'a EnemyRegistry> {
+        registries.get(&self.registry)
+    }
+
+    pub fn sprite(&self, index: usize) -> Handle<Image> {
+        self.sprites.get(index).cloned().unwrap_or_default()
+    }
+}
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<EnemyRegistry>()
+            .init_asset_loader::<EnemyRegistryLoader>()
+            .add_startup_system(load_registry)
+            .add_system(load_enemy_sprites);
+    }
+}
+
+fn load_registry(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Enemies {
+        registry: asset_server.load(REGISTRY_PATH),
+        sprites: Vec::new(),
+    });
+}
+
+fn load_enemy_sprites(
+    mut registry_events: EventReader<AssetEvent<EnemyRegistry>>,
+    registries: Res<Assets<EnemyRegistry>>,
+    mut enemies: ResMut<Enemies>,
+    asset_server: Res<AssetServer>,
+    images: Option<Res<Assets<Image>>>,
+) {
+    for event in registry_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        if *handle != enemies.registry {
+            continue;
+        }
+
+        let registry = match registries.get(handle) {
+            Some(registry) => registry,
+            None => continue,
+        };
+        info!("Loaded {} enemy types", registry.types.len());
+
+        if images.is_some() {
+            enemies.sprites = registry.types.iter().map(|t| asset_server.load(t.sprite.as_str())).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(weights: &[u32]) -> EnemyRegistry {
+        EnemyRegistry {
+            types: weights
+                .iter()
+                .enumerate()
+                .map(|(i, weight)| EnemyType {
+                    name: format!("type {}", i),
+                    sprite: String::new(),
+                    color: white(),
+                    size: (1., 2.),
+                    size_growth: 0.,
+                    speed: 1.,
+                    density: 1.,
+                    damage: 1,
+                    behavior: EnemyBehavior::Chase,
+                    weight: *weight,
+                })
+                .collect(),
+            hash: 0,
+        }
+    }
+
+    fn picked(registry: &EnemyRegistry, roll: u32) -> Option<usize> {
+        registry.pick(roll).map(|(index, _)| index)
+    }
+
+    #[test]
+    fn picks_at_the_weight_boundaries() {
+        let registry = registry(&[3, 1, 6]);
+        assert_eq!(registry.total_weight(), 10);
+        assert_eq!(picked(&registry, 0), Some(0));
+        assert_eq!(picked(&registry, 2), Some(0));
+        assert_eq!(picked(&registry, 3), Some(1));
+        assert_eq!(picked(&registry, 4), Some(2));
+        assert_eq!(picked(&registry, 9), Some(2));
+        assert_eq!(picked(&registry, 10), None);
+    }
+
+    #[test]
+    fn never_picks_zero_weights() {
+        let registry = registry(&[0, 2, 0, 1]);
+        assert_eq!(picked(&registry, 0), Some(1));
+        assert_eq!(picked(&registry, 1), Some(1));
+        assert_eq!(picked(&registry, 2), Some(3));
+        assert_eq!(picked(&registry, 3), None);
+    }
+
+    #[test]
+    fn empty_registry_picks_nothing() {
+        let registry = registry(&[]);
+        assert_eq!(registry.total_weight(), 0);
+        assert_eq!(picked(&registry, 0), None);
+    }
+}
@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::Rng;
+
+use crate::{
+    boss::Boss, difficulty::Difficulty, enemies::Damage, is_enemy, is_player, show_chaser_count, spawn_chaser,
+    ChaserCount, ChasingEnemy, Chicken, EnemyCountText, PlayArea, RandomGenerator, SizeScale,
+};
+
+// A big meteor that hits another one or a player hard enough breaks into a few smaller
+// ones. The pieces add up to roughly the same mass, and fly apart evenly around the
+// old meteor's velocity, so between them they carry on with the same momentum.
+// The pieces look like the meteor they came from and hit as hard, but always chase.
+// Bosses break apart their own way, see `boss`, and chickens never do.
+
+// Meteors smaller than this only ever bounce
+const MIN_SIZE_SCALE: f32 = 1.6;
+// How fast the two bodies have to be moving towards each other
+const MIN_IMPACT_SPEED: f32 = 350.;
+const MIN_PIECE_SCALE: f32 = 0.6;
+// How fast the pieces move apart, on top of the old meteor's velocity
+const PIECE_SPREAD_SPEED: f32 = 120.;
+
+// Uses `RandomGenerator`, collision events arrive in the same order every time the run is simulated
+pub fn split_meteors(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut random_gen: ResMut<RandomGenerator>,
+    (difficulty, play_area): (Res<Difficulty>, Res<PlayArea>),
+    mut chaser_count: ResMut<ChaserCount>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+    chaser_query: Query<
+        (&Transform, &Velocity, &SizeScale, &Handle<Image>, &Sprite, Option<&Damage>),
+        (With<ChasingEnemy>, Without<Boss>, Without<Chicken>),
+    >,
+    velocity_query: Query<&Velocity>,
+) {
+    let window_width = play_area.0.x;
+    let mut split = Vec::new();
+
+    for event in events.iter().filter(|event| event.is_started()) {
+        let (layers_1, layers_2) = event.collision_layers();
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let pairs = [(entity_1, layers_1, entity_2, layers_2), (entity_2, layers_2, entity_1, layers_1)];
+
+        for (meteor, meteor_layers, other, other_layers) in pairs {
+            if !is_enemy(meteor_layers) || !(is_enemy(other_layers) || is_player(other_layers)) {
+                continue;
+            }
+            // The meteor itself makes room for one of its pieces, and a split needs at least two
+            let room = (chaser_count.max + 1).saturating_sub(chaser_count.current);
+            if split.contains(&meteor) || room < 2 {
+                continue;
+            }
+
+            let (transform, velocity, size_scale, texture, sprite, damage) = match chaser_query.get(meteor) {
+                Ok((transform, velocity, SizeScale(size_scale), texture, sprite, damage))
+                    if *size_scale >= MIN_SIZE_SCALE =>
+                {
+                    (transform, velocity, *size_scale, texture, sprite, damage)
+                }
+                _ => continue,
+            };
+            let other_velocity = velocity_query.get(other).map_or(Vec3::ZERO, |v| v.linear);
+            if (velocity.linear - other_velocity).truncate().length() < MIN_IMPACT_SPEED {
+                continue;
+            }
+
+            // Mass goes with the cube of `SizeScale`, since bigger meteors are also denser
+            let pieces = random_gen.0.gen_range(2..=4_u32).min(room);
+            let piece_scale = (size_scale / (pieces as f32).cbrt()).max(MIN_PIECE_SCALE);
+            let first_angle = random_gen.0.gen_range(0.0..std::f32::consts::TAU);
+            let center = transform.translation.truncate();
+            let distance = window_width / 80. * (size_scale - piece_scale);
+
+            for i in 0..pieces {
+                let angle = first_angle + i as f32 * std::f32::consts::TAU / pieces as f32;
+                let direction = Vec2::new(angle.cos(), angle.sin());
+                let piece = spawn_chaser(
+                    &mut commands,
+                    texture.clone(),
+                    center + direction * distance,
+                    piece_scale,
+                    difficulty.settings().chaser_speed,
+                    window_width,
+                );
+                let piece_velocity = velocity.linear + (direction * PIECE_SPREAD_SPEED).extend(0.);
+                let piece_size = window_width / 40. * piece_scale;
+                commands
+                    .entity(piece)
+                    .insert(Velocity::from_linear(piece_velocity))
+                    .insert(Sprite {
+                        color: sprite.color,
+                        custom_size: Some(Vec2::new(piece_size, piece_size)),
+                        ..Default::default()
+                    });
+                if let Some(Damage(damage)) = damage {
+                    commands.entity(piece).insert(Damage(*damage));
+                }
+            }
+
+            commands.entity(meteor).despawn();
+            chaser_count.current += pieces - 1;
+            split.push(meteor);
+        }
+    }
+
+    if !split.is_empty() {
+        show_chaser_count(&chaser_count, enemy_count_text_query.iter_mut());
+    }
+}
@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::CheatsUsed,
+    coop::PlayerCount,
+    difficulty::Difficulty,
+    high_scores::SurvivalTime,
+    menu::InMenu,
+    net::offline,
+    replay::{GameTick, ReplayState},
+    settings::Settings,
+    storage, PlayerDied, PlayerSlot, RestartRun, RunSeed,
+};
+
+// The player's path is sampled every `SAMPLE_INTERVAL` seconds of the run. When a run
+// on a seed and difficulty beats the saved best, its path replaces the saved one and
+// a see-through Earth follows it the next time that seed comes up. The ghost is only
+// a sprite, it has no rigid body or collision layers so it can't touch anything.
+// Co-op runs neither race a ghost nor leave one behind.
+
+const GHOSTS_KEY: &str = "ghosts";
+const SAMPLE_INTERVAL: f32 = 0.1;
+// Only the most recently improved seeds are kept, to keep the save small
+const MAX_GHOSTS: usize = 10;
+const GHOST_Z: f32 = -0.01;
+const GHOST_ALPHA: f32 = 0.35;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ghost {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub seconds: f32,
+    // Rounded to whole pixels, that's plenty for something see-through
+    pub path: Vec<(i32, i32)>,
+}
+
+impl Ghost {
+    fn position_at(&self, seconds: f32) -> Option<Vec2> {
+        let sample = seconds / SAMPLE_INTERVAL;
+        let index = sample.floor() as usize;
+        let (x1, y1) = *self.path.get(index)?;
+        let (x2, y2) = *self.path.get(index + 1).unwrap_or(&(x1, y1));
+        let from = Vec2::new(x1 as f32, y1 as f32);
+        let to = Vec2::new(x2 as f32, y2 as f32);
+        Some(from.lerp(to, sample.fract()))
+    }
+}
+
+// Best runs by seed and difficulty, oldest first
+#[derive(Default)]
+pub struct Ghosts(Vec<Ghost>);
+
+impl Ghosts {
+    fn load() -> Self {
+        Ghosts(
+            storage::load(GHOSTS_KEY)
+                .and_then(|contents| ron::from_str(&contents).ok())
+                .unwrap_or_default(),
+        )
+    }
+
+    fn save(&self) {
+        match ron::to_string(&self.0) {
+            Ok(contents) => storage::save(GHOSTS_KEY, &contents),
+            Err(e) => warn!("Could not serialize ghosts: {}", e),
+        }
+    }
+
+    pub fn best(&self, seed: u64, difficulty: Difficulty) -> Option<&Ghost> {
+        self.0.iter().find(|g| g.seed == seed && g.difficulty == difficulty)
+    }
+}
+
+// The path of the run being played and the ghost racing it
+#[derive(Default)]
+struct GhostRun {
+    path: Vec<(i32, i32)>,
+    racing: Option<Ghost>,
+}
+
+#[derive(Component)]
+struct GhostSprite;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Ghosts::load())
+            .init_resource::<GhostRun>()
+            .add_startup_system(spawn_ghost)
+            .add_system(start_ghost_run.label("start_ghost_run"))
+            .add_system(record_ghost_path.after("start_ghost_run"))
+            .add_system(save_ghost.with_run_criteria(offline))
+            .add_system(move_ghost);
+    }
+}
+
+fn spawn_ghost(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load("sprites/PlayerEarth.png"),
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, GHOST_Z),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(GhostSprite);
+}
+
+// `RunSeed` is picked before `Update`, so it already belongs to the new run here
+fn start_ghost_run(
+    mut restart_events: EventReader<RestartRun>,
+    mut run: ResMut<GhostRun>,
+    ghosts: Res<Ghosts>,
+    run_seed: Res<RunSeed>,
+    difficulty: Res<Difficulty>,
+    player_count: Res<PlayerCount>,
+) {
+    if restart_events.iter().count() > 0 {
+        *run = GhostRun {
+            path: Vec::new(),
+            racing: if player_count.0 == 1 { ghosts.best(run_seed.0, *difficulty).cloned() } else { None },
+        };
+    }
+}
+
+fn record_ghost_path(
+    tick: Res<GameTick>,
+    survival_time: Res<SurvivalTime>,
+    mut run: ResMut<GhostRun>,
+    player_count: Res<PlayerCount>,
+    player_query: Query<(&Transform, &PlayerSlot)>,
+) {
+    if !tick.running || player_count.0 > 1 {
+        return;
+    }
+
+    if survival_time.0 >= run.path.len() as f32 * SAMPLE_INTERVAL {
+        if let Some((transform, _)) = player_query.iter().find(|(_, PlayerSlot(slot))| *slot == 0) {
+            let position = transform.translation;
+            run.path.push((position.x.round() as i32, position.y.round() as i32));
+        }
+    }
+}
+
+fn save_ghost(
+    player_died: Res<PlayerDied>,
+    survival_time: Res<SurvivalTime>,
+    run_seed: Res<RunSeed>,
+    difficulty: Res<Difficulty>,
+    replay_state: Res<ReplayState>,
+    cheats_used: Res<CheatsUsed>,
+    mut run: ResMut<GhostRun>,
+    mut ghosts: ResMut<Ghosts>,
+) {
+    if !player_died.is_changed() || !player_died.0 || replay_state.is_playing() || run.path.is_empty() {
+        return;
+    }
+    // Nobody could race a ghost that didn't have to dodge anything
+    if cheats_used.this_run {
+        return;
+    }
+
+    let previous_best = ghosts.best(run_seed.0, *difficulty).map(|g| g.seconds).unwrap_or(0.);
+    if survival_time.0 <= previous_best {
+        return;
+    }
+
+    ghosts.0.retain(|g| g.seed != run_seed.0 || g.difficulty != *difficulty);
+    ghosts.0.push(Ghost {
+        seed: run_seed.0,
+        difficulty: *difficulty,
+        seconds: survival_time.0,
+        path: std::mem::take(&mut run.path),
+    });
+    if ghosts.0.len() > MAX_GHOSTS {
+        let excess = ghosts.0.len() - MAX_GHOSTS;
+        ghosts.0.drain(..excess);
+    }
+    ghosts.save();
+}
+
+fn move_ghost(
+    run: Res<GhostRun>,
+    settings: Res<Settings>,
+    in_menu: Res<InMenu>,
+    survival_time: Res<SurvivalTime>,
+    windows: Res<Windows>,
+    mut ghost_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<GhostSprite>>,
+) {
+    let (mut transform, mut sprite, mut visibility) = ghost_query.single_mut();
+
+    let position = match &run.racing {
+        Some(ghost) if settings.show_ghost && !in_menu.0 => ghost.position_at(survival_time.0),
+        _ => None,
+    };
+
+    match position {
+        Some(position) => {
+            transform.translation = position.extend(GHOST_Z);
+            // Same size as the player, see `add_player` and `resize_items`
+            let size = windows.get_primary().unwrap().width() / 20.;
+            sprite.custom_size = Some(Vec2::new(size, size));
+            visibility.is_visible = true;
+        }
+        None => visibility.is_visible = false,
+    }
+}
@@ -0,0 +1,412 @@
+use bevy::{prelude::*, utils::HashSet};
+use heron::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    arena::Arena,
+    coop::{player_bounds, MAX_PLAYERS, PLAYER_COLORS},
+    net::offline,
+    replay::GameTick,
+    shapes::GeneratedShapes,
+    starfield::mix,
+    ChasingEnemy, Chicken, Downed, Layer, PlayArea, Player, PlayerDied, PlayerSlot, RestartRun, RunSeed,
+    PHYSICS_TIME_SCALE,
+};
+
+// Orbital mode scatters small star systems through space, each a sun with a planet or
+// two going round it. Every sun and planet pulls on the players and the meteors through
+// heron's `Acceleration`, so passing close to a moving planet can fling a player off
+// much faster than they could fly, and the meteors fall into the same orbits.
+// A dotted line shows where each player is headed if they stop steering.
+//
+// Star systems are laid out by hashing the run seed with a grid cell, the same as
+// `hazards`, and planets go round on a clock that only counts game time, so replays
+// play out the same. Like hazards they aren't part of the network protocol.
+
+// How wide a cell is, in play area widths
+const CELL_SIZE: f32 = 2.5;
+const SYSTEM_CHANCE: f64 = 0.5;
+// Cells this far from the players' cell have their star systems in place
+const PLACE_RANGE: i32 = 2;
+const KEEP_RANGE: i32 = 3;
+// No sun this close to where the players start, in play area widths
+const START_CLEARANCE: f32 = 0.8;
+const BODY_Z: f32 = -0.03;
+const TRAJECTORY_Z: f32 = -0.01;
+
+// How hard a body pulls one play area width away from it, in play area widths per
+// second every second. It goes up with the inverse square of the distance from there.
+const SUN_PULL: f32 = 0.02;
+const PLANET_PULL: f32 = 0.006;
+// Compared to a meteor with a `SizeScale` of 1
+const SUN_SCALE: f32 = 3.;
+const PLANET_SCALE: f32 = 1.5;
+const PLANET_COLORS: [Color; 4] = [
+    Color::rgb(0.35, 0.55, 0.95),
+    Color::rgb(0.85, 0.45, 0.3),
+    Color::rgb(0.55, 0.8, 0.5),
+    Color::rgb(0.8, 0.7, 0.9),
+];
+
+// Seconds of game time the trajectory looks ahead, and the dots it's drawn with
+const PREDICTION_TIME: f32 = 2.;
+const TRAJECTORY_DOTS: usize = 30;
+const STEPS_PER_DOT: usize = 2;
+
+// Picked in the menu, see `menu_input`
+pub struct Gravity {
+    pub enabled: bool,
+    // Cells near the players that have been filled in, whether they got a star system or not
+    cells: HashSet<(i32, i32)>,
+    // Fixed for the whole run when the first cells are filled in
+    cell_size: f32,
+    // Game time the planets have been going round for this run
+    clock: f32,
+}
+
+// A sun or a planet. `pull` is in units per second every second at one unit away.
+#[derive(Component)]
+struct GravityWell {
+    pull: f32,
+    // Bodies closer than this are pulled as if they were this far, which is also the size of the body
+    radius: f32,
+    cell: (i32, i32),
+}
+
+#[derive(Component, Clone, Copy)]
+struct Orbit {
+    center: Vec2,
+    radius: f32,
+    phase: f32,
+    // Radians per second, negative for clockwise
+    angular_speed: f32,
+}
+
+impl Orbit {
+    fn position(&self, clock: f32) -> Vec2 {
+        let angle = self.phase + self.angular_speed * clock;
+        self.center + Vec2::new(angle.cos(), angle.sin()) * self.radius
+    }
+}
+
+#[derive(Component)]
+struct TrajectoryDot {
+    slot: usize,
+    index: usize,
+}
+
+pub struct GravityPlugin;
+
+impl Plugin for GravityPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Gravity {
+                enabled: false,
+                cells: HashSet::default(),
+                cell_size: 0.,
+                clock: 0.,
+            })
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_trajectory_dots)
+            .add_system(reset_gravity.label("reset_gravity"))
+            .add_system(place_star_systems.after("reset_gravity").with_run_criteria(offline))
+            .add_system(move_planets.label("move_planets").with_run_criteria(offline))
+            .add_system(apply_gravity.after("move_planets").with_run_criteria(offline))
+            .add_system(draw_trajectories.after("move_planets"));
+    }
+}
+
+fn reset_gravity(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartRun>,
+    mut gravity: ResMut<Gravity>,
+    well_query: Query<Entity, With<GravityWell>>,
+) {
+    if restart_events.iter().count() > 0 {
+        well_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+        gravity.cells.clear();
+        gravity.cell_size = 0.;
+        gravity.clock = 0.;
+    }
+}
+
+fn place_star_systems(
+    mut commands: Commands,
+    mut gravity: ResMut<Gravity>,
+    run_seed: Res<RunSeed>,
+    play_area: Res<PlayArea>,
+    (shapes, arena): (Res<GeneratedShapes>, Res<Arena>),
+    player_query: Query<&Transform, (With<Player>, Without<Downed>)>,
+    well_query: Query<(Entity, &GravityWell)>,
+) {
+    if !gravity.enabled {
+        if !gravity.cells.is_empty() {
+            well_query.iter().for_each(|(e, _)| commands.entity(e).despawn_recursive());
+            gravity.cells.clear();
+        }
+        return;
+    }
+
+    let center = match player_bounds(player_query.iter().map(|t| t.translation.truncate())) {
+        Some((center, _)) => center,
+        None => return,
+    };
+
+    let window_width = play_area.0.x;
+    if gravity.cell_size <= 0. {
+        gravity.cell_size = window_width * CELL_SIZE;
+    }
+    let cell_size = gravity.cell_size;
+    if cell_size <= 0. {
+        return;
+    }
+
+    let current = ((center.x / cell_size).floor() as i32, (center.y / cell_size).floor() as i32);
+    let within = |cell: (i32, i32), range: i32| (cell.0 - current.0).abs() <= range && (cell.1 - current.1).abs() <= range;
+
+    for (entity, well) in well_query.iter() {
+        if !within(well.cell, KEEP_RANGE) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    gravity.cells.retain(|cell| within(*cell, KEEP_RANGE));
+
+    for x in (current.0 - PLACE_RANGE)..=(current.0 + PLACE_RANGE) {
+        for y in (current.1 - PLACE_RANGE)..=(current.1 + PLACE_RANGE) {
+            let cell = (x, y);
+            if !gravity.cells.insert(cell) {
+                continue;
+            }
+
+            // Salted so a cell's star system doesn't line up with its hazard
+            let mut rng = StdRng::seed_from_u64(mix(mix(mix(run_seed.0 ^ 0x6772_6176) ^ x as u32 as u64) ^ y as u32 as u64));
+            if !rng.gen_bool(SYSTEM_CHANCE) {
+                continue;
+            }
+
+            let sun = Vec2::new(
+                (x as f32 + rng.gen_range(0.3..0.7)) * cell_size,
+                (y as f32 + rng.gen_range(0.3..0.7)) * cell_size,
+            );
+            if sun.length() < window_width * START_CLEARANCE || !arena.contains(play_area.0, sun) {
+                continue;
+            }
+
+            spawn_star_system(&mut commands, &shapes, &mut rng, cell, sun, window_width);
+        }
+    }
+}
+
+fn spawn_star_system(
+    commands: &mut Commands,
+    shapes: &GeneratedShapes,
+    rng: &mut StdRng,
+    cell: (i32, i32),
+    sun: Vec2,
+    window_width: f32,
+) {
+    // Pulls are given per play area width so they feel the same at any window size
+    let per_width = |pull: f32| pull * window_width * window_width * window_width;
+    let sun_size = window_width / 40. * SUN_SCALE;
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: shapes.circle.clone(),
+            sprite: Sprite {
+                color: Color::rgb(1.0, 0.8, 0.3),
+                custom_size: Some(Vec2::new(sun_size, sun_size)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(sun.extend(BODY_Z)),
+            ..Default::default()
+        })
+        .insert(GravityWell { pull: per_width(SUN_PULL), radius: sun_size / 2., cell })
+        .insert(RigidBody::Static)
+        .insert(CollisionShape::Sphere { radius: sun_size / 2. })
+        .insert(body_layers())
+        .with_children(|parent| {
+            parent.spawn_bundle(SpriteBundle {
+                texture: shapes.ring.clone(),
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 0.6, 0.2, 0.6),
+                    custom_size: Some(Vec2::new(sun_size * 1.4, sun_size * 1.4)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+
+    let planet_size = window_width / 40. * PLANET_SCALE;
+    for _ in 0..rng.gen_range(0..=2) {
+        let orbit = Orbit {
+            center: sun,
+            radius: window_width * rng.gen_range(0.35..0.7),
+            phase: rng.gen_range(0.0..std::f32::consts::TAU),
+            angular_speed: rng.gen_range(0.25..0.6) * if rng.gen_bool(0.5) { 1. } else { -1. },
+        };
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: shapes.circle.clone(),
+                sprite: Sprite {
+                    color: PLANET_COLORS[rng.gen_range(0..PLANET_COLORS.len())],
+                    custom_size: Some(Vec2::new(planet_size, planet_size)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(orbit.position(0.).extend(BODY_Z)),
+                ..Default::default()
+            })
+            .insert(GravityWell { pull: per_width(PLANET_PULL), radius: planet_size / 2., cell })
+            .insert(orbit)
+            .insert(RigidBody::KinematicPositionBased)
+            .insert(CollisionShape::Sphere { radius: planet_size / 2. })
+            .insert(body_layers());
+    }
+}
+
+// Suns and planets are solid, but only to players, meteors and chickens
+fn body_layers() -> CollisionLayers {
+    CollisionLayers::new(Layer::World, Layer::Player)
+        .with_mask(Layer::Enemies)
+        .with_mask(Layer::Pickups)
+}
+
+fn move_planets(
+    tick: Res<GameTick>,
+    mut gravity: ResMut<Gravity>,
+    mut planet_query: Query<(&Orbit, &mut Transform)>,
+) {
+    if !gravity.enabled || !tick.running {
+        return;
+    }
+
+    gravity.clock += tick.delta.as_secs_f32();
+    for (orbit, mut transform) in planet_query.iter_mut() {
+        transform.translation = orbit.position(gravity.clock).extend(BODY_Z);
+    }
+}
+
+// The acceleration at `position` from every body in `wells`, given as (position, pull, radius)
+fn pull_at(position: Vec2, wells: impl Iterator<Item = (Vec2, f32, f32)>) -> Vec2 {
+    wells.fold(Vec2::ZERO, |total, (well, pull, radius)| {
+        let offset = well - position;
+        let distance = offset.length().max(radius);
+        total + offset.normalize_or_zero() * pull / (distance * distance)
+    })
+}
+
+fn apply_gravity(
+    mut commands: Commands,
+    gravity: Res<Gravity>,
+    well_query: Query<(&Transform, &GravityWell)>,
+    mut body_query: Query<
+        (Entity, &Transform, Option<&mut Acceleration>, Option<&Downed>),
+        (Or<(With<Player>, With<ChasingEnemy>, With<Chicken>)>, Without<GravityWell>),
+    >,
+) {
+    let wells: Vec<(Vec2, f32, f32)> = well_query
+        .iter()
+        .map(|(transform, well)| (transform.translation.truncate(), well.pull, well.radius))
+        .collect();
+
+    for (entity, transform, acceleration, downed) in body_query.iter_mut() {
+        let linear = if gravity.enabled && downed.is_none() {
+            pull_at(transform.translation.truncate(), wells.iter().copied()).extend(0.)
+        } else {
+            Vec3::ZERO
+        };
+
+        match acceleration {
+            Some(mut acceleration) => {
+                if acceleration.linear != linear {
+                    acceleration.linear = linear;
+                }
+            }
+            None if linear != Vec3::ZERO => {
+                commands.entity(entity).insert(Acceleration::from_linear(linear));
+            }
+            None => {}
+        }
+    }
+}
+
+fn spawn_trajectory_dots(mut commands: Commands, shapes: Res<GeneratedShapes>) {
+    for slot in 0..MAX_PLAYERS {
+        for index in 0..TRAJECTORY_DOTS {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: shapes.circle.clone(),
+                    transform: Transform::from_xyz(0., 0., TRAJECTORY_Z),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(TrajectoryDot { slot, index });
+        }
+    }
+}
+
+// Steps each player forward the way physics would with only gravity and damping acting
+// on them, and the planets carrying on round their suns. The line stops at the first body it hits.
+fn draw_trajectories(
+    gravity: Res<Gravity>,
+    player_died: Res<PlayerDied>,
+    play_area: Res<PlayArea>,
+    well_query: Query<(&Transform, &GravityWell, Option<&Orbit>)>,
+    player_query: Query<(&PlayerSlot, &Transform, &Velocity, &Damping), (With<Player>, Without<Downed>)>,
+    mut dot_query: Query<
+        (&TrajectoryDot, &mut Transform, &mut Sprite, &mut Visibility),
+        (Without<Player>, Without<GravityWell>),
+    >,
+) {
+    let mut paths: [Vec<Vec2>; MAX_PLAYERS] = Default::default();
+
+    if gravity.enabled && !player_died.0 {
+        let wells: Vec<(Vec2, f32, f32, Option<Orbit>)> = well_query
+            .iter()
+            .map(|(transform, well, orbit)| (transform.translation.truncate(), well.pull, well.radius, orbit.copied()))
+            .collect();
+        let step = PREDICTION_TIME / (TRAJECTORY_DOTS * STEPS_PER_DOT) as f32;
+        let physics_step = step * PHYSICS_TIME_SCALE;
+
+        for (PlayerSlot(slot), transform, velocity, damping) in player_query.iter() {
+            let mut position = transform.translation.truncate();
+            let mut velocity = velocity.linear.truncate();
+
+            'path: for dot in 0..TRAJECTORY_DOTS {
+                for substep in 0..STEPS_PER_DOT {
+                    let clock = gravity.clock + (dot * STEPS_PER_DOT + substep + 1) as f32 * step;
+                    let wells_then = wells.iter().map(|(position, pull, radius, orbit)| {
+                        (orbit.map_or(*position, |orbit| orbit.position(clock)), *pull, *radius)
+                    });
+                    velocity += pull_at(position, wells_then.clone()) * physics_step;
+                    velocity /= 1. + physics_step * damping.linear;
+                    position += velocity * physics_step;
+
+                    if wells_then.into_iter().any(|(well, _, radius)| well.distance(position) < radius) {
+                        break 'path;
+                    }
+                }
+                paths[*slot].push(position);
+            }
+        }
+    }
+
+    let dot_size = play_area.0.x / 150.;
+    for (TrajectoryDot { slot, index }, mut transform, mut sprite, mut visibility) in dot_query.iter_mut() {
+        match paths[*slot].get(*index) {
+            Some(position) => {
+                let fade = 1. - *index as f32 / TRAJECTORY_DOTS as f32;
+                let mut color = PLAYER_COLORS[*slot];
+                color.set_a(0.8 * fade);
+                transform.translation = position.extend(TRAJECTORY_Z);
+                sprite.color = color;
+                sprite.custom_size = Some(Vec2::new(dot_size, dot_size));
+                visibility.is_visible = true;
+            }
+            None => {
+                if visibility.is_visible {
+                    visibility.is_visible = false;
+                }
+            }
+        }
+    }
+}
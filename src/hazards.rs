@@ -0,0 +1,463 @@
+use bevy::{prelude::*, utils::HashSet};
+use heron::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    arena::Arena,
+    boss::Boss,
+    coop::player_bounds,
+    is_player,
+    net::offline,
+    particles::{ParticleBurst, ParticleEffect},
+    replay::GameTick,
+    shapes::GeneratedShapes,
+    show_chaser_count,
+    starfield::mix,
+    ChaserCount, ChaserSprite, ChasingEnemy, Chicken, Downed, EnemyCountText, GodMode, Layer, PlayArea, Player,
+    PlayerDied, PlayerHealth, PlayerHit, RestartRun, RunSeed, SizeScale,
+};
+
+// Space around the players is split into a grid of cells, and each cell may hold one
+// hazard: a black hole that pulls everything in and swallows the meteors that fall all
+// the way, a solar flare sweeping round its star, or a belt of asteroids that stands in
+// the way like a wall. Meteors get caught by them just like players do, so leading the
+// swarm past one is a way to thin it out.
+//
+// What a cell holds comes from hashing the run seed with the cell, like `starfield`, so
+// it's the same hazard whenever the players come back to it, and in replays too.
+// Hazards aren't part of the network protocol, so online games are played without them.
+
+// How wide a cell is, in play area widths
+const CELL_SIZE: f32 = 1.5;
+// The chance of a cell having a hazard in it
+const HAZARD_CHANCE: f64 = 0.6;
+// Cells this far from the players' cell have their hazards in place, which is always off screen
+const PLACE_RANGE: i32 = 2;
+// Cells further away than this are forgotten until the players come back
+const KEEP_RANGE: i32 = 3;
+// Nothing is placed this close to where the players start, in play area widths
+const START_CLEARANCE: f32 = 0.6;
+// In front of the stars, behind the meteor trails
+const HAZARD_Z: f32 = -0.03;
+
+// How far a black hole pulls from, in play area widths
+const PULL_RADIUS: f32 = 0.45;
+// In units per second every second, right at the middle. Players can always climb back
+// out given long enough, most meteors that get close can't.
+const PULL_STRENGTH: f32 = 250.;
+// The size of the part that swallows things, compared to a meteor with a `SizeScale` of 1
+const CORE_SCALE: f32 = 1.5;
+// How fast a player who touches the core gets thrown back out
+const ESCAPE_SPEED: f32 = 600.;
+
+const FLARE_LENGTH: f32 = 0.5;
+// Radians per second
+const FLARE_SPEED: f32 = 0.7;
+const STAR_SCALE: f32 = 2.;
+
+const BELT_LENGTH: f32 = 0.8;
+
+pub struct Hazards {
+    pub enabled: bool,
+    // Cells near the players that have been filled in, whether they got a hazard or not
+    cells: HashSet<(i32, i32)>,
+    // Fixed for the whole run when the first cells are filled in, so resizing the window doesn't move them
+    cell_size: f32,
+}
+
+// On every part of every hazard, so they can be cleared out a cell at a time
+#[derive(Component)]
+pub struct Hazard {
+    cell: (i32, i32),
+}
+
+#[derive(Component)]
+struct BlackHole {
+    radius: f32,
+}
+
+#[derive(Component)]
+struct SolarFlare {
+    pivot: Vec2,
+    length: f32,
+    angle: f32,
+    angular_speed: f32,
+}
+
+impl SolarFlare {
+    // The flare's sprite and collider are centered halfway along it
+    fn transform(&self) -> Transform {
+        let direction = Vec2::new(self.angle.cos(), self.angle.sin());
+        Transform {
+            translation: (self.pivot + direction * self.length / 2.).extend(HAZARD_Z),
+            rotation: Quat::from_rotation_z(self.angle),
+            ..Default::default()
+        }
+    }
+}
+
+pub struct HazardPlugin;
+
+impl Plugin for HazardPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Hazards {
+                enabled: true,
+                cells: HashSet::default(),
+                cell_size: 0.,
+            })
+            .add_system(reset_hazards.label("reset_hazards"))
+            .add_system(place_hazards.after("reset_hazards").with_run_criteria(offline))
+            .add_system(pull_into_black_holes.with_run_criteria(offline))
+            .add_system(sweep_solar_flares.with_run_criteria(offline))
+            .add_system(hazard_collisions.with_run_criteria(offline));
+    }
+}
+
+// Hazards touch players, meteors and chickens, but never each other
+fn hazard_layers() -> CollisionLayers {
+    CollisionLayers::new(Layer::World, Layer::Player)
+        .with_mask(Layer::Enemies)
+        .with_mask(Layer::Pickups)
+}
+
+fn is_hazard(layers: CollisionLayers) -> bool {
+    layers.contains_group(Layer::World) && !layers.contains_group(Layer::Player) && !layers.contains_group(Layer::Enemies)
+}
+
+fn reset_hazards(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartRun>,
+    mut hazards: ResMut<Hazards>,
+    hazard_query: Query<Entity, With<Hazard>>,
+) {
+    if restart_events.iter().count() > 0 {
+        hazard_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+        hazards.cells.clear();
+        hazards.cell_size = 0.;
+    }
+}
+
+fn place_hazards(
+    mut commands: Commands,
+    mut hazards: ResMut<Hazards>,
+    run_seed: Res<RunSeed>,
+    play_area: Res<PlayArea>,
+    (shapes, chaser_sprite, arena): (Res<GeneratedShapes>, Res<ChaserSprite>, Res<Arena>),
+    player_query: Query<&Transform, (With<Player>, Without<Downed>)>,
+    hazard_query: Query<(Entity, &Hazard)>,
+) {
+    // `hazards off` in the console takes away the ones already out there as well
+    if !hazards.enabled {
+        if !hazards.cells.is_empty() {
+            hazard_query.iter().for_each(|(e, _)| commands.entity(e).despawn_recursive());
+            hazards.cells.clear();
+        }
+        return;
+    }
+
+    let center = match player_bounds(player_query.iter().map(|t| t.translation.truncate())) {
+        Some((center, _)) => center,
+        None => return,
+    };
+
+    let window_width = play_area.0.x;
+    if hazards.cell_size <= 0. {
+        hazards.cell_size = window_width * CELL_SIZE;
+    }
+    let cell_size = hazards.cell_size;
+    if cell_size <= 0. {
+        return;
+    }
+
+    let current = ((center.x / cell_size).floor() as i32, (center.y / cell_size).floor() as i32);
+    let within = |cell: (i32, i32), range: i32| (cell.0 - current.0).abs() <= range && (cell.1 - current.1).abs() <= range;
+
+    for (entity, hazard) in hazard_query.iter() {
+        if !within(hazard.cell, KEEP_RANGE) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    hazards.cells.retain(|cell| within(*cell, KEEP_RANGE));
+
+    for x in (current.0 - PLACE_RANGE)..=(current.0 + PLACE_RANGE) {
+        for y in (current.1 - PLACE_RANGE)..=(current.1 + PLACE_RANGE) {
+            let cell = (x, y);
+            if !hazards.cells.insert(cell) {
+                continue;
+            }
+
+            let mut rng = StdRng::seed_from_u64(mix(mix(run_seed.0 ^ x as u32 as u64) ^ y as u32 as u64));
+            if !rng.gen_bool(HAZARD_CHANCE) {
+                continue;
+            }
+
+            // Kept away from the edges so hazards in neighbouring cells don't overlap much
+            let position = Vec2::new(
+                (x as f32 + rng.gen_range(0.25..0.75)) * cell_size,
+                (y as f32 + rng.gen_range(0.25..0.75)) * cell_size,
+            );
+            if position.length() < window_width * START_CLEARANCE || !arena.contains(play_area.0, position) {
+                continue;
+            }
+
+            match rng.gen_range(0..3) {
+                0 => spawn_black_hole(&mut commands, &shapes, cell, position, window_width),
+                1 => spawn_solar_flare(&mut commands, &shapes, &mut rng, cell, position, window_width),
+                _ => spawn_asteroid_belt(&mut commands, &chaser_sprite.0, &mut rng, cell, position, window_width),
+            }
+        }
+    }
+}
+
+fn spawn_black_hole(commands: &mut Commands, shapes: &GeneratedShapes, cell: (i32, i32), position: Vec2, window_width: f32) {
+    let core_size = window_width / 40. * CORE_SCALE;
+    let radius = window_width * PULL_RADIUS;
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: shapes.circle.clone(),
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::new(core_size, core_size)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(position.extend(HAZARD_Z)),
+            ..Default::default()
+        })
+        .insert(Hazard { cell })
+        .insert(BlackHole { radius })
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere { radius: core_size / 2. })
+        .insert(hazard_layers())
+        .with_children(|parent| {
+            // A glowing rim, since the hole itself is as black as space
+            parent.spawn_bundle(SpriteBundle {
+                texture: shapes.ring.clone(),
+                sprite: Sprite {
+                    color: Color::rgb(1.0, 0.55, 0.15),
+                    custom_size: Some(Vec2::new(core_size * 1.3, core_size * 1.3)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0., 0., 0.001),
+                ..Default::default()
+            });
+            // And a faint one showing how far the pull reaches
+            parent.spawn_bundle(SpriteBundle {
+                texture: shapes.ring.clone(),
+                sprite: Sprite {
+                    color: Color::rgba(0.6, 0.35, 1.0, 0.15),
+                    custom_size: Some(Vec2::new(radius * 2., radius * 2.)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+}
+
+fn spawn_solar_flare(
+    commands: &mut Commands,
+    shapes: &GeneratedShapes,
+    rng: &mut StdRng,
+    cell: (i32, i32),
+    pivot: Vec2,
+    window_width: f32,
+) {
+    let star_size = window_width / 40. * STAR_SCALE;
+    let length = window_width * FLARE_LENGTH;
+    let thickness = window_width / 60.;
+
+    // The star is solid, only the flare burns
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: shapes.circle.clone(),
+            sprite: Sprite {
+                color: Color::rgb(1.0, 0.85, 0.3),
+                custom_size: Some(Vec2::new(star_size, star_size)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(pivot.extend(HAZARD_Z + 0.001)),
+            ..Default::default()
+        })
+        .insert(Hazard { cell })
+        .insert(RigidBody::Static)
+        .insert(CollisionShape::Sphere { radius: star_size / 2. })
+        .insert(hazard_layers());
+
+    let flare = SolarFlare {
+        pivot,
+        length,
+        angle: rng.gen_range(0.0..std::f32::consts::TAU),
+        angular_speed: if rng.gen_bool(0.5) { FLARE_SPEED } else { -FLARE_SPEED },
+    };
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(1.0, 0.5, 0.1, 0.85),
+                custom_size: Some(Vec2::new(length, thickness)),
+                ..Default::default()
+            },
+            transform: flare.transform(),
+            ..Default::default()
+        })
+        .insert(Hazard { cell })
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::new(length / 2., thickness / 2., 0.),
+            border_radius: None,
+        })
+        .insert(hazard_layers())
+        .insert(flare);
+}
+
+// A line of rocks with gaps that small meteors can slip through but players can't
+fn spawn_asteroid_belt(
+    commands: &mut Commands,
+    texture: &Handle<Image>,
+    rng: &mut StdRng,
+    cell: (i32, i32),
+    center: Vec2,
+    window_width: f32,
+) {
+    let angle = rng.gen_range(0.0..std::f32::consts::PI);
+    let direction = Vec2::new(angle.cos(), angle.sin());
+    let across = direction.perp();
+    let length = window_width * BELT_LENGTH;
+    let rocks = rng.gen_range(8..=14_u32);
+
+    for i in 0..rocks {
+        let along = (i as f32 / (rocks - 1) as f32 - 0.5) * length;
+        let offset = rng.gen_range(-1.0..1.0) * window_width / 80.;
+        let size = window_width / 40. * rng.gen_range(1.0..1.8);
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: texture.clone(),
+                sprite: Sprite {
+                    color: Color::rgb(0.45, 0.42, 0.4),
+                    custom_size: Some(Vec2::new(size, size)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation((center + direction * along + across * offset).extend(HAZARD_Z)),
+                ..Default::default()
+            })
+            .insert(Hazard { cell })
+            .insert(RigidBody::Static)
+            .insert(CollisionShape::Sphere { radius: size / 2. })
+            .insert(hazard_layers());
+    }
+}
+
+// Everything falls in at the same rate whatever its size, like real gravity
+fn pull_into_black_holes(
+    tick: Res<GameTick>,
+    hole_query: Query<(&Transform, &BlackHole)>,
+    mut body_query: Query<
+        (&Transform, &mut Velocity),
+        (Or<(With<Player>, With<ChasingEnemy>, With<Chicken>)>, Without<BlackHole>, Without<Downed>),
+    >,
+) {
+    if !tick.running {
+        return;
+    }
+
+    let delta = tick.delta.as_secs_f32();
+    for (hole_transform, BlackHole { radius }) in hole_query.iter() {
+        let hole = hole_transform.translation.truncate();
+        for (transform, mut velocity) in body_query.iter_mut() {
+            let offset = hole - transform.translation.truncate();
+            let distance = offset.length();
+            if distance >= *radius || distance == 0. {
+                continue;
+            }
+            let pull = offset / distance * PULL_STRENGTH * (1. - distance / radius) * delta;
+            velocity.linear += pull.extend(0.);
+        }
+    }
+}
+
+fn sweep_solar_flares(tick: Res<GameTick>, mut flare_query: Query<(&mut SolarFlare, &mut Transform)>) {
+    if !tick.running {
+        return;
+    }
+
+    let delta = tick.delta.as_secs_f32();
+    for (mut flare, mut transform) in flare_query.iter_mut() {
+        flare.angle = (flare.angle + flare.angular_speed * delta).rem_euclid(std::f32::consts::TAU);
+        *transform = flare.transform();
+    }
+}
+
+// Black holes swallow meteors and chickens and flares burn them up. Either one takes a
+// heart off a player who touches it, and a black hole throws them back out as well.
+// Bosses are too big for either, see `boss::damage_bosses` for what happens to them.
+fn hazard_collisions(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    (player_died, god_mode): (Res<PlayerDied>, Res<GodMode>),
+    mut chaser_count: ResMut<ChaserCount>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+    hazard_query: Query<(&Transform, Option<&BlackHole>), Or<(With<BlackHole>, With<SolarFlare>)>>,
+    victim_query: Query<(&Transform, &SizeScale, Option<&ChasingEnemy>), (Without<Boss>, Without<Player>)>,
+    mut player_query: Query<(&Transform, &mut PlayerHealth, &mut Velocity), With<Player>>,
+    mut hit_events: EventWriter<PlayerHit>,
+    mut bursts: EventWriter<ParticleBurst>,
+) {
+    let mut removed = Vec::new();
+
+    for event in events.iter().filter(|event| event.is_started()) {
+        let (layers_1, layers_2) = event.collision_layers();
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (hazard, other, other_layers) = if is_hazard(layers_1) {
+            (entity_1, entity_2, layers_2)
+        } else if is_hazard(layers_2) {
+            (entity_2, entity_1, layers_1)
+        } else {
+            continue;
+        };
+
+        // Stars and asteroid belts are only in the way
+        let (hazard_transform, black_hole) = match hazard_query.get(hazard) {
+            Ok(hazard) => hazard,
+            Err(_) => continue,
+        };
+
+        if is_player(other_layers) {
+            let (transform, mut health, mut velocity) = match player_query.get_mut(other) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+            if black_hole.is_some() {
+                let away = (transform.translation - hazard_transform.translation).truncate().normalize_or_zero();
+                let away = if away == Vec2::ZERO { Vec2::X } else { away };
+                velocity.linear = (away * ESCAPE_SPEED).extend(0.);
+            }
+            if !player_died.0 && !god_mode.0 && health.0 > 0 {
+                health.0 -= 1;
+                hit_events.send(PlayerHit);
+            }
+            continue;
+        }
+
+        if removed.contains(&other) {
+            continue;
+        }
+        if let Ok((transform, SizeScale(size_scale), chaser)) = victim_query.get(other) {
+            commands.entity(other).despawn();
+            removed.push(other);
+            // Chickens were never counted
+            if chaser.is_some() {
+                chaser_count.current = chaser_count.current.saturating_sub(1);
+            }
+            bursts.send(ParticleBurst {
+                effect: ParticleEffect::Impact,
+                position: transform.translation.truncate(),
+                scale: *size_scale,
+            });
+        }
+    }
+
+    if !removed.is_empty() {
+        show_chaser_count(&chaser_count, enemy_count_text_query.iter_mut());
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    console::CheatsUsed,
+    coop::PlayerCount,
+    daily::{DailyChallenge, DailyEntry, DailyScores},
+    difficulty::Difficulty,
+    replay::{GameTick, ReplayState},
+    versus::Versus,
+    ChaserCount, PlayerDied, RunSeed, SubCenterText,
+};
+
+const MAX_ENTRIES_PER_DIFFICULTY: usize = 5;
+
+// Seconds survived in the current run. Does not count time spent paused or in the menu.
+pub struct SurvivalTime(pub f32);
+
+#[derive(Clone, Copy)]
+pub struct HighScoreEntry {
+    pub difficulty: Difficulty,
+    pub seconds: f32,
+    pub chasers: u32,
+}
+
+// Each difficulty keeps its own table so an Easy run never pushes a Hard run off the board
+#[derive(Default)]
+pub struct HighScores {
+    tables: HashMap<Difficulty, Vec<HighScoreEntry>>,
+}
+
+impl HighScores {
+    pub fn table(&self, difficulty: Difficulty) -> &[HighScoreEntry] {
+        self.tables.get(&difficulty).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn best(&self, difficulty: Difficulty) -> Option<HighScoreEntry> {
+        self.table(difficulty).first().copied()
+    }
+
+    // Returns the placement of the new entry, if it made it onto the table
+    pub fn submit(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let table = self.tables.entry(entry.difficulty).or_insert_with(Vec::new);
+        let position = table
+            .iter()
+            .position(|e| entry.seconds > e.seconds)
+            .unwrap_or(table.len());
+
+        if position >= MAX_ENTRIES_PER_DIFFICULTY {
+            return None;
+        }
+
+        table.insert(position, entry);
+        table.truncate(MAX_ENTRIES_PER_DIFFICULTY);
+        Some(position)
+    }
+
+    pub fn format_table(&self, difficulty: Difficulty) -> String {
+        let table = self.table(difficulty);
+        if table.is_empty() {
+            return String::from("No runs yet");
+        }
+
+        table
+            .iter()
+            .enumerate()
+            .map(|(i, e)| format!("{}. {:.1}s  ({} enemies)", i + 1, e.seconds, e.chasers))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub fn track_survival_time(mut survival_time: ResMut<SurvivalTime>, tick: Res<GameTick>) {
+    if tick.running {
+        survival_time.0 += tick.delta.as_secs_f32();
+    }
+}
+
+pub fn record_high_score(
+    player_died: Res<PlayerDied>,
+    survival_time: Res<SurvivalTime>,
+    difficulty: Res<Difficulty>,
+    chaser_count: Res<ChaserCount>,
+    mut high_scores: ResMut<HighScores>,
+    mut sub_center_text: Query<&mut Text, With<SubCenterText>>,
+    replay_state: Res<ReplayState>,
+    daily: Res<DailyChallenge>,
+    mut daily_scores: ResMut<DailyScores>,
+    run_seed: Res<RunSeed>,
+    player_count: Res<PlayerCount>,
+    versus: Res<Versus>,
+    cheats_used: Res<CheatsUsed>,
+) {
+    if player_died.is_changed() && player_died.0 {
+        // Versus rounds have a winner rather than a time, see `versus::announce_winner`
+        if versus.enabled {
+            return;
+        }
+
+        // Watching a replay again shouldn't fill the table with copies of the same run
+        if replay_state.is_playing() {
+            sub_center_text.single_mut().sections[0].value = format!(
+                "Replay over after {:.1}s\nPress R to restart or M for menu",
+                survival_time.0,
+            );
+            return;
+        }
+
+        if cheats_used.this_run {
+            sub_center_text.single_mut().sections[0].value = format!(
+                "Survived {:.1}s with cheats, so it doesn't count\nPress R to restart or M for menu",
+                survival_time.0,
+            );
+            return;
+        }
+
+        // The tables are for single player runs, two players last a lot longer
+        if player_count.0 > 1 {
+            sub_center_text.single_mut().sections[0].value = format!(
+                "Co-op run on {} lasted {:.1}s\nPress R to restart or M for menu",
+                difficulty.name(),
+                survival_time.0,
+            );
+            return;
+        }
+
+        // Daily runs only go on the daily table, with what's needed to compare them with other players
+        if let Some(date) = daily.date {
+            let placement = daily_scores.submit(date, DailyEntry {
+                seconds: survival_time.0,
+                chasers: chaser_count.current,
+            });
+            let best = daily_scores.best(date).map(|e| e.seconds).unwrap_or(survival_time.0);
+
+            let summary = match placement {
+                Some(0) => format!("New daily best: {:.1}s!", survival_time.0),
+                _ => format!("Survived {:.1}s - Today's best {:.1}s", survival_time.0, best),
+            };
+            sub_center_text.single_mut().sections[0].value = format!(
+                "{}\nDaily challenge {} - seed {}\nPress R to retry or M for menu",
+                summary, date, run_seed.0,
+            );
+            return;
+        }
+
+        let placement = high_scores.submit(HighScoreEntry {
+            difficulty: *difficulty,
+            seconds: survival_time.0,
+            chasers: chaser_count.current,
+        });
+
+        let summary = match placement {
+            Some(0) => format!("New {} best: {:.1}s!", difficulty.name(), survival_time.0),
+            _ => format!(
+                "Survived {:.1}s on {} - Best {:.1}s",
+                survival_time.0,
+                difficulty.name(),
+                high_scores.best(*difficulty).map(|e| e.seconds).unwrap_or(survival_time.0),
+            ),
+        };
+
+        sub_center_text.single_mut().sections[0].value =
+            format!("{}\nPress R to restart or M for menu", summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(difficulty: Difficulty, seconds: f32) -> HighScoreEntry {
+        HighScoreEntry { difficulty, seconds, chasers: 0 }
+    }
+
+    #[test]
+    fn keeps_the_best_five_in_order() {
+        let mut scores = HighScores::default();
+        for seconds in [30., 10., 50., 20., 40.] {
+            assert!(scores.submit(entry(Difficulty::Normal, seconds)).is_some());
+        }
+        assert_eq!(scores.submit(entry(Difficulty::Normal, 5.)), None);
+        assert_eq!(scores.submit(entry(Difficulty::Normal, 60.)), Some(0));
+
+        let table: Vec<f32> = scores.table(Difficulty::Normal).iter().map(|e| e.seconds).collect();
+        assert_eq!(table, vec![60., 50., 40., 30., 20.]);
+    }
+
+    #[test]
+    fn ties_go_below_the_earlier_run() {
+        let mut scores = HighScores::default();
+        scores.submit(entry(Difficulty::Hard, 20.));
+        assert_eq!(scores.submit(entry(Difficulty::Hard, 20.)), Some(1));
+    }
+
+    #[test]
+    fn difficulties_have_their_own_tables() {
+        let mut scores = HighScores::default();
+        scores.submit(entry(Difficulty::Easy, 100.));
+        assert_eq!(scores.submit(entry(Difficulty::Insane, 1.)), Some(0));
+        assert_eq!(scores.best(Difficulty::Easy).map(|e| e.seconds), Some(100.));
+        assert!(scores.table(Difficulty::Normal).is_empty());
+    }
+}
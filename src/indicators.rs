@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+
+use crate::{camera::CameraView, shapes::GeneratedShapes, ChasingEnemy, SizeScale};
+
+// Indicators live in world space just in front of everything else, and get
+// pinned to the edge of whatever the camera currently sees
+const INDICATOR_Z: f32 = 10.0;
+const EDGE_MARGIN: f32 = 24.0;
+
+pub struct ThreatIndicatorSettings {
+    pub count: usize,
+}
+
+impl Default for ThreatIndicatorSettings {
+    fn default() -> Self {
+        ThreatIndicatorSettings { count: 8 }
+    }
+}
+
+pub struct ThreatIndicatorPlugin;
+
+impl Plugin for ThreatIndicatorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ThreatIndicatorSettings>()
+            .add_system(sync_indicator_pool.label("sync_indicator_pool"))
+            .add_system(
+                update_threat_indicators
+                    .after("sync_indicator_pool")
+                    .after("camera"),
+            );
+    }
+}
+
+#[derive(Component)]
+struct ThreatIndicator(usize);
+
+// Keeps exactly `count` indicator sprites alive so the count can be changed at runtime
+fn sync_indicator_pool(
+    mut commands: Commands,
+    settings: Res<ThreatIndicatorSettings>,
+    shapes: Res<GeneratedShapes>,
+    indicator_query: Query<(Entity, &ThreatIndicator)>,
+) {
+    let existing = indicator_query.iter().count();
+
+    if existing < settings.count {
+        for id in existing..settings.count {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: shapes.arrow.clone(),
+                    transform: Transform::from_xyz(0.0, 0.0, INDICATOR_Z),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(ThreatIndicator(id));
+        }
+    } else if existing > settings.count {
+        indicator_query
+            .iter()
+            .filter(|(_, ThreatIndicator(id))| *id >= settings.count)
+            .for_each(|(e, _)| commands.entity(e).despawn());
+    }
+}
+
+fn update_threat_indicators(
+    windows: Res<Windows>,
+    view: Res<CameraView>,
+    chaser_query: Query<(&Transform, &SizeScale), (With<ChasingEnemy>, Without<ThreatIndicator>)>,
+    mut indicator_query: Query<(&ThreatIndicator, &mut Transform, &mut Sprite, &mut Visibility)>,
+    settings: Res<ThreatIndicatorSettings>,
+) {
+    let window = windows.get_primary().unwrap();
+    let camera = view.center;
+    let half_extents = view.half_extents;
+
+    let mut threats: Vec<(f32, Vec2, f32)> = chaser_query
+        .iter()
+        .filter_map(|(transform, SizeScale(size_scale))| {
+            let offset = transform.translation.truncate() - camera;
+            let on_screen = offset.x.abs() <= half_extents.x && offset.y.abs() <= half_extents.y;
+            if on_screen {
+                None
+            } else {
+                Some((offset.length_squared(), offset, *size_scale))
+            }
+        })
+        .collect();
+
+    let count = settings.count.min(threats.len());
+    if count > 0 && count < threats.len() {
+        threats.select_nth_unstable_by(count - 1, |a, b| a.0.partial_cmp(&b.0).unwrap());
+    }
+    threats.truncate(count);
+
+    // Matches the chaser size used by `spawn_chasers` and `resize_items`, kept the
+    // same size on screen when the camera zooms out
+    let base_size = window.width() / 40. * view.zoom;
+    let inner_extents = half_extents - Vec2::splat(EDGE_MARGIN * view.zoom);
+
+    for (ThreatIndicator(id), mut transform, mut sprite, mut visibility) in indicator_query.iter_mut() {
+        if let Some((_, offset, size_scale)) = threats.get(*id) {
+            // Scale the direction until it touches the inside of the screen edge
+            let scale = (inner_extents.x / offset.x.abs()).min(inner_extents.y / offset.y.abs());
+            let edge_position = camera + *offset * scale;
+
+            transform.translation = edge_position.extend(INDICATOR_Z);
+            transform.rotation = Quat::from_rotation_z(offset.y.atan2(offset.x) - std::f32::consts::FRAC_PI_2);
+
+            let size = base_size * 0.75 * size_scale.clamp(0.75, 2.5);
+            sprite.custom_size = Some(Vec2::new(size, size));
+
+            // Red when the meteor is about to come into view, fading to yellow further out
+            let distance_past_edge = offset.length() * (1.0 - scale);
+            let closeness = 1.0 - (distance_past_edge / (window.width() * view.zoom)).clamp(0.0, 1.0);
+            sprite.color = Color::rgba(1.0, 0.9 - 0.8 * closeness, 0.2 * (1.0 - closeness), 0.5 + 0.4 * closeness);
+
+            visibility.is_visible = true;
+        } else {
+            visibility.is_visible = false;
+        }
+    }
+}
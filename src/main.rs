@@ -3,90 +3,258 @@ use rand::{Rng, SeedableRng};
 use bevy::{
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     prelude::*, 
-    window::{WindowMode, WindowResizeConstraints, WindowResized},
+    window::{WindowFocused, WindowResizeConstraints, WindowResized},
     ui::Val::Px, app::Events,
 };
 
 use heron::prelude::*;
 
+mod arena;
+mod audio;
+mod boss;
+mod camera;
+mod chickens;
+mod console;
+mod daily;
+mod date;
+mod controls;
+mod coop;
+#[cfg(feature = "debug_overlay")]
+mod debug_overlay;
+mod difficulty;
+mod enemies;
+mod fragments;
+mod ghost;
+mod gravity;
+mod hazards;
+mod high_scores;
+mod indicators;
+mod menu;
+mod net;
+#[cfg(not(target_arch = "wasm32"))]
+mod net_client;
+#[cfg(not(target_arch = "wasm32"))]
+mod net_server;
+mod particles;
+mod pause_menu;
+mod physics_debug;
+mod radar;
+mod replay;
+mod replay_browser;
+mod settings;
+mod settings_menu;
+mod shapes;
+mod starfield;
+mod storage;
+mod versus;
+
+use arena::{Arena, ArenaPlugin};
+use audio::GameAudioPlugin;
+use boss::BossPlugin;
+use camera::CameraPlugin;
+use chickens::{ChickenPlugin, ChickenSettings};
+use console::ConsolePlugin;
+use daily::{DailyChallenge, DailyPlugin};
+use controls::InputAction;
+use coop::{CoopPlugin, PlayerCount, PLAYER_COLORS};
+use difficulty::Difficulty;
+use enemies::{Damage, EnemyBehavior, EnemyPlugin, EnemyRegistry, Enemies, DRIFT_SPEED};
+use ghost::GhostPlugin;
+use gravity::GravityPlugin;
+use hazards::HazardPlugin;
+use high_scores::{HighScores, SurvivalTime};
+use indicators::ThreatIndicatorPlugin;
+use menu::InMenu;
+use net::NetMode;
+use particles::ParticlePlugin;
+use pause_menu::PauseMenuPlugin;
+use physics_debug::PhysicsDebugPlugin;
+use radar::{RadarPlugin, RadarSettings};
+use replay::{GameTick, ReplayPlugin};
+use replay_browser::ReplayBrowserPlugin;
+use settings::{DisplayMode, Settings};
+use settings_menu::SettingsMenuPlugin;
+use starfield::StarfieldPlugin;
+use versus::{Versus, VersusPlugin};
+
 fn main() {
-    App::new()
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::args().any(|arg| arg == "--server") {
+        let port = command_line_value("--server").and_then(|port| port.parse().ok()).unwrap_or(net::DEFAULT_PORT);
+        // Exits non-zero so whatever started the server can tell it didn't come up
+        if let Err(e) = net_server::run_headless(port) {
+            eprintln!("Could not listen on port {}: {}", port, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let settings = Settings::load();
+
+    let mut app = App::new();
+    app
         .insert_resource(ClearColor(Color::rgba(0.0, 0.0, 0.0, 1.0)))
         .insert_resource(
             WindowDescriptor {
                 transparent: false,
                 decorations: true,
-                mode: WindowMode::Windowed,
                 title: "Earth Escape".to_string(),
-                width: 1200.,
-                height: 800.,
                 resize_constraints: WindowResizeConstraints {
                     min_height: 400.0,
                     min_width: 400.0,
                     ..Default::default()
                 },
-                ..Default::default()
+                ..settings.window_descriptor()
             }
         )
+        .insert_resource(settings)
         .add_plugins(DefaultPlugins)
         .add_plugin(PhysicsPlugin::default())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_plugin(CameraPlugin)
+        .add_plugin(StarfieldPlugin)
+        .add_plugin(ThreatIndicatorPlugin)
+        .add_plugin(RadarPlugin)
+        .add_plugin(ParticlePlugin)
+        .add_plugin(PauseMenuPlugin)
+        .add_plugin(SettingsMenuPlugin)
+        .add_plugin(GameAudioPlugin)
+        .add_plugin(ConsolePlugin)
+        .add_plugin(PhysicsDebugPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(ReplayBrowserPlugin)
+        .add_plugin(GhostPlugin)
+        .add_plugin(DailyPlugin)
+        .add_plugin(CoopPlugin)
+        .add_plugin(VersusPlugin)
+        .add_plugin(BossPlugin)
+        .add_plugin(ChickenPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(HazardPlugin)
+        .add_plugin(GravityPlugin)
+        .add_plugin(ArenaPlugin)
+        .add_startup_system_to_stage(StartupStage::PreStartup, shapes::create_shapes)
+        .add_event::<RestartRun>()
+        .add_event::<PlayerHit>()
+        .add_event::<SetPaused>()
+        .add_event::<SpawnChasers>()
+        .init_resource::<NextRunSeed>()
+        .init_resource::<GodMode>()
+        .init_resource::<PlayArea>()
+        .insert_resource(NetMode::Offline)
+        .insert_resource(Difficulty::Normal)
+        .insert_resource(HighScores::default())
         .add_startup_system(setup)
         .add_startup_system(add_player)
         .add_system(player_movement)
-        .add_system(move_chasing_enemies)
-        .add_system(spawn_chasers)
-        // .add_system(text_update_system)
+        // A server runs these when playing online, see `net`
+        .add_system(move_chasing_enemies.with_run_criteria(net::offline))
+        .add_system(spawn_chasers.with_run_criteria(net::offline))
+        .add_system(fragments::split_meteors.with_run_criteria(net::offline))
+        .add_system(text_update_system)
+        .add_system(toggle_fps_text)
+        .add_system(settings::apply_settings)
         .add_system(toggle_physics_pause)
+        .add_system(pause_on_focus_loss)
+        .add_system_to_stage(CoreStage::PreUpdate, apply_pause.label("apply_pause"))
+        .add_system_to_stage(CoreStage::PreUpdate, track_play_area)
         .add_system(fullscreen_toggle)
-        .add_system(calculate_health)
+        .add_system(calculate_health.label("calculate_health"))
+        .add_system(update_downed_players.after("calculate_health"))
+        .add_system(restart_input.with_run_criteria(net::offline))
+        .add_system_to_stage(CoreStage::PreUpdate, reseed_random_generator.label("reseed_random_generator"))
         .add_system(reset_game)
+        .add_system(reset_players)
+        .add_system(rebuild_hearts)
         .add_system(resize_items)
-        .add_system(increase_spawn_size)
+        .add_system(increase_spawn_size.with_run_criteria(net::offline))
+        .add_system(menu::sync_menu)
+        .add_system(menu::menu_input.with_run_criteria(net::offline))
+        .add_system(menu::update_menu_text)
+        .add_system(high_scores::track_survival_time)
+        .add_system(
+            high_scores::record_high_score
+                .label("record_high_score")
+                .with_run_criteria(net::offline),
+        )
         //.add_system(text_color_system)
-        .run();
+        .add_system(update_difficulty_text);
+
+    #[cfg(feature = "debug_overlay")]
+    app.add_plugin(debug_overlay::DebugOverlayPlugin);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(address) = command_line_value("--connect") {
+        app.add_plugin(net_client::NetClientPlugin { address });
+    }
+
+    app.run();
+}
+
+// The value after `flag` on the command line, e.g. `--replay saves/replays/1760790000.replay`
+fn command_line_value(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
 }
 
 #[derive(Component)]
 struct Player;
 
+// Which player this is, 0 for player one. Indexes `GameTick::actions`.
+#[derive(Component)]
+struct PlayerSlot(usize);
+
+// A co-op player who ran out of hearts while the other one is still going
+#[derive(Component)]
+struct Downed;
+
 #[derive(Component)]
 struct ChasingEnemy;
 
 #[derive(Component)]
 struct Speed(f32);
 
-// The u8 represents the placement of the heart
+// The easter egg that uses `ChickenSprite`. It runs from players rather than chasing them, see `chickens`.
+#[derive(Component)]
+struct Chicken;
+
+// The player slot the heart belongs to, and its placement in that player's row
 #[derive(Component)]
-struct HeartSprite(u8);
+struct HeartSprite(usize, u8);
 
 // Define your physics layers
 // Probably only need one or none
 #[derive(PhysicsLayer)]
 enum Layer {
+    // Black holes, flares and asteroid belts, see `hazards`
     World,
     Player,
     Enemies,
+    // Chickens, which only touch players
+    Pickups,
 }
 
 fn resize_items(
     resize_event: Res<Events<WindowResized>>,
     mut player_query: Query<(&mut Sprite, &mut CollisionShape), (With<Player>, Without<ChasingEnemy>)>,
-    mut chaser_query: Query<(&mut Sprite, &mut CollisionShape, &SizeScale), (With<ChasingEnemy>, Without<Player>)>,
+    mut chaser_query: Query<
+        (&mut Sprite, &mut CollisionShape, &SizeScale),
+        (Or<(With<ChasingEnemy>, With<Chicken>)>, Without<Player>),
+    >,
 ) {
     let mut reader = resize_event.get_reader();
     for e in reader.iter(&resize_event) {
         let player_size = e.width / 20.;
-        let (mut sprite, mut shape, ) = player_query.single_mut();
-        sprite.custom_size = Some(Vec2::new(player_size, player_size));
-        *shape =
-            // CollisionShape::Cuboid {
-            //     half_extends: Vec3::new(player_size / 2., player_size / 2., 0.0),
-            //     border_radius: None,
-            // };
-            CollisionShape::Sphere {
-                radius: player_size / 2.,
-            };
+        for (mut sprite, mut shape) in player_query.iter_mut() {
+            sprite.custom_size = Some(Vec2::new(player_size, player_size));
+            *shape =
+                // CollisionShape::Cuboid {
+                //     half_extends: Vec3::new(player_size / 2., player_size / 2., 0.0),
+                //     border_radius: None,
+                // };
+                CollisionShape::Sphere {
+                    radius: player_size / 2.,
+                };
+        }
         
         for (mut sprite, mut shape, SizeScale(size_scale)) in chaser_query.iter_mut() {
             let chaser_size = e.width / 40.;
@@ -104,22 +272,35 @@ fn resize_items(
 }
 
 fn add_player(mut commands: Commands, windows: Res<Windows>, asset_server: Res<AssetServer>) {
+    let window_width = windows.get_primary().unwrap().width();
+    spawn_player(&mut commands, asset_server.load("sprites/PlayerEarth.png"), 0, 1, window_width, 5);
+}
 
-    let size = windows.get_primary().unwrap().width() / 20.;
+fn spawn_player(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    slot: usize,
+    player_count: usize,
+    window_width: f32,
+    hearts: u8,
+) {
+    let size = window_width / 20.;
 
     commands
         .spawn_bundle(
             SpriteBundle {
                 sprite: Sprite {
+                    color: PLAYER_COLORS[slot],
                     custom_size: Some(Vec2::new(size, size)),
                     ..Default::default()
                 },
-                texture: asset_server.load("sprites/PlayerEarth.png"),
-                transform: Transform::from_xyz(0.0, 0.0, 0.0),
+                texture,
+                transform: Transform::from_translation(coop::start_position(slot, player_count, window_width)),
                 ..Default::default()
             }
         )
         .insert(Player)
+        .insert(PlayerSlot(slot))
         .insert(Speed(5.0))
         .insert(RigidBody::Dynamic)
         
@@ -140,104 +321,225 @@ fn add_player(mut commands: Commands, windows: Res<Windows>, asset_server: Res<A
         .insert(PhysicMaterial { friction: 1.0, density: 20.0, ..Default::default() })
         .insert(Damping::from_linear(0.5).with_angular(1.0))
         .insert(RotationConstraints::lock())
-        .insert(CollisionLayers::new(Layer::Player, Layer::Enemies))
-        .insert(PlayerHealth(5));
+        .insert(player_layers())
+        .insert(PlayerHealth(hearts));
+}
+
+fn player_layers() -> CollisionLayers {
+    CollisionLayers::new(Layer::Player, Layer::Enemies)
+        .with_mask(Layer::Pickups)
+        .with_mask(Layer::World)
 }
 
 struct SpawnTimer(Timer);
 
 struct PlayerDied(bool);
 
+// Sent to start a fresh run, either from the menu or by restarting after death
+struct RestartRun;
+
+// Sent each time a meteor takes a heart away
+struct PlayerHit;
+
+// Pauses or resumes gameplay. Ignored while dead or in the menu.
+struct SetPaused(bool);
+
+// Spawns chasers right away, on top of the ones from `SpawnTimer`. Sent by the console.
+struct SpawnChasers {
+    count: u32,
+    size_scale: Option<f32>,
+}
+
 #[derive(Component)]
 struct SizeScale(f32);
 
 fn spawn_chasers(
     mut commands: Commands,
     mut timer: ResMut<SpawnTimer>,
-    time: Res<Time>,
+    tick: Res<GameTick>,
     mut chaser_count: ResMut<ChaserCount>,
     mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
-    windows: Res<Windows>,
-    chaser_sprite: Res<ChaserSprite>,
-    chicken_sprite: Res<ChickenSprite>,
+    (play_area, arena): (Res<PlayArea>, Res<Arena>),
+    (enemies, registries): (Res<Enemies>, Res<Assets<EnemyRegistry>>),
+    (chicken_sprite, chicken_settings): (Res<ChickenSprite>, Res<ChickenSettings>),
     mut random_gen: ResMut<RandomGenerator>,
-    player_query: Query<&Transform, With<Player>>,
+    player_query: Query<&Transform, (With<Player>, Without<Downed>)>,
     player_died: Res<PlayerDied>,
     game_paused: ResMut<GamePaused>,
     size_increments: Res<SpawnSizeIncrements>,
+    difficulty: Res<Difficulty>,
+    mut forced_spawns: EventReader<SpawnChasers>,
 ) {
-    if !game_paused.0 && !player_died.0 && timer.0.tick(time.delta()).just_finished() && !chaser_count.at_max() {
+    // Each entry is one chaser to spawn this frame, with its size if it was asked for
+    let mut spawns: Vec<Option<f32>> = forced_spawns
+        .iter()
+        .flat_map(|e| std::iter::repeat(e.size_scale).take(e.count as usize))
+        .collect();
+    if !game_paused.0 && !player_died.0 && timer.0.tick(tick.delta).just_finished() {
+        spawns.push(None);
+    }
 
-        let window_width = windows.get_primary().unwrap().width();
-        let window_height = windows.get_primary().unwrap().height();
+    for forced_size_scale in spawns {
+        if chaser_count.at_max() {
+            break;
+        }
 
-        let size = window_width / 40.;
+        let window_width = play_area.0.x;
+        let window_height = play_area.0.y;
 
-        let player_transform = player_query.single().translation;
+        // Meteors spawn just off screen around all the players, see `update_camera`
+        let (player_center, player_spread) =
+            match coop::player_bounds(player_query.iter().map(|t| t.translation.truncate())) {
+                Some(bounds) => bounds,
+                None => break,
+            };
+
+        // Offline runs don't start until `assets/data/meteors.enemies.ron` has loaded, see
+        // `replay::advance_tick`, so this only skips spawns on the server
+        let registry = match enemies.loaded(&registries) {
+            Some(registry) if registry.total_weight() > 0 => registry,
+            _ => break,
+        };
+        let (type_index, enemy_type) = match registry.pick(random_gen.0.gen_range(0..registry.total_weight())) {
+            Some(picked) => picked,
+            None => break,
+        };
 
         let size_scale =
-            if random_gen.0.gen_bool(0.75) { 
-                random_gen.0.gen_range(0.8..1.2)
-            } else { 
-                random_gen.0.gen_range(0.75..2.5 + (size_increments.0 as f32 / 50.))
+            if let Some(size_scale) = forced_size_scale {
+                size_scale
+            } else {
+                let (min, max) = enemy_type.size;
+                let max = max + enemy_type.size_growth * size_increments.0 as f32;
+                if max > min { random_gen.0.gen_range(min..max) } else { min }
             };
 
+        let reach_x = player_spread.x + window_width;
+        let reach_y = player_spread.y + window_height;
+
         let spawn_x: f32 = 
             if random_gen.0.gen_bool(0.5) { 
-                random_gen.0.gen_range((player_transform.x + window_width)..(player_transform.x + window_width + 100.)) 
+                random_gen.0.gen_range((player_center.x + reach_x)..(player_center.x + reach_x + 100.)) 
             } else { 
-                random_gen.0.gen_range((player_transform.x - window_width - 100.)..(player_transform.x - window_width)) 
+                random_gen.0.gen_range((player_center.x - reach_x - 100.)..(player_center.x - reach_x)) 
             };
 
         let spawn_y: f32 = 
             if random_gen.0.gen_bool(0.5) { 
-                random_gen.0.gen_range((player_transform.y + window_height)..(player_transform.y + window_height + 100.)) 
+                random_gen.0.gen_range((player_center.y + reach_y)..(player_center.y + reach_y + 100.)) 
             } else {
-                random_gen.0.gen_range((player_transform.y - window_height - 100.)..(player_transform.y - window_height)) 
+                random_gen.0.gen_range((player_center.y - reach_y - 100.)..(player_center.y - reach_y)) 
             };
 
+        // In an arena there's no off screen, so they come in from just inside the walls
+        let position = if arena.shape.is_some() {
+            let players: Vec<Vec2> = player_query.iter().map(|t| t.translation.truncate()).collect();
+            arena.spawn_point(&mut random_gen.0, play_area.0, window_width / 40. * size_scale, &players)
+        } else {
+            Vec2::new(spawn_x, spawn_y)
+        };
+
+        // Chickens don't count towards `ChaserCount`
+        if random_gen.0.gen_bool(chicken_settings.spawn_chance.clamp(0., 1.)) {
+            chickens::spawn_chicken(&mut commands, chicken_sprite.0.clone(), position, window_width);
+            continue;
+        }
+
+        let chaser = spawn_chaser(
+            &mut commands,
+            enemies.sprite(type_index),
+            position,
+            size_scale,
+            difficulty.settings().chaser_speed * enemy_type.speed,
+            window_width,
+        );
+
+        let size = window_width / 40. * size_scale;
+        let (red, green, blue) = enemy_type.color;
         commands
-            .spawn_bundle(
-                SpriteBundle {
-                    texture: if random_gen.0.gen_bool(0.01) { chicken_sprite.0.clone() } else { chaser_sprite.0.clone() },
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::new(size * size_scale, size * size_scale)),
-                        ..Default::default()
-                    },
-                    transform: Transform::from_xyz(spawn_x, spawn_y, 0.0),
-                    ..Default::default()
-                }
-            )
-            .insert(ChasingEnemy)
-            .insert(Speed(2.5))
-            .insert(RigidBody::Dynamic)
-            .insert(SizeScale(size_scale))
-                    
-            // Attach a collision shape
-            // .insert(CollisionShape::Cuboid {
-            //     half_extends: Vec3::new(size / 2., size / 2., 0.0),
-            //     border_radius: None,
-            // })
-            .insert(CollisionShape::Sphere {
-                radius: (size * size_scale) / 2.,
+            .entity(chaser)
+            .insert(Sprite {
+                color: Color::rgb(red, green, blue),
+                custom_size: Some(Vec2::new(size, size)),
+                ..Default::default()
             })
+            .insert(PhysicMaterial { friction: 1.0, density: enemy_type.density * size_scale, ..Default::default() })
+            .insert(Damage(enemy_type.damage))
+            .insert(enemy_type.behavior);
+
+        // Drifters get all their speed up front, since `move_chasing_enemies` leaves them alone
+        if enemy_type.behavior == EnemyBehavior::Drift {
+            let direction = (player_center - position).normalize_or_zero();
+            let speed = difficulty.settings().chaser_speed * enemy_type.speed * DRIFT_SPEED;
+            commands.entity(chaser).insert(Velocity::from_linear((direction * speed).extend(0.)));
+        }
 
-            // // Optionally add other useful components...
-            .insert(Velocity::default())
-            // .insert(Velocity::from_linear(Vec3::X * 2.0))
-            // .insert(Acceleration::from_linear(Vec3::X * -1.0))
-            .insert(PhysicMaterial { friction: 1.0, density: 10.0 * size_scale, ..Default::default() })
-            //.insert(RotationConstraints::lock())
-            .insert(CollisionLayers::new(Layer::Enemies, Layer::Player).with_mask(Layer::Enemies));
-            
-            chaser_count.current += 1;
-            enemy_count_text_query.single_mut().sections[1].style.color = Color::Rgba {
-                red: 1.,
-                green: (255. - chaser_count.current as f32) / 255.,
-                blue: (255. - chaser_count.current as f32) / 255.,
-                alpha: 1.
-            };
-            enemy_count_text_query.single_mut().sections[1].value = format!("{:.2}", chaser_count.current);
+        chaser_count.current += 1;
+        // The headless server has no text to update
+        show_chaser_count(&chaser_count, enemy_count_text_query.iter_mut());
+    }
+}
+
+// A single meteor at `position`. Whoever calls this also counts it in `ChaserCount`.
+fn spawn_chaser(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    position: Vec2,
+    size_scale: f32,
+    speed: f32,
+    window_width: f32,
+) -> Entity {
+    let size = window_width / 40.;
+
+    commands
+        .spawn_bundle(
+            SpriteBundle {
+                texture,
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(size * size_scale, size * size_scale)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(position.extend(0.0)),
+                ..Default::default()
+            }
+        )
+        .insert(ChasingEnemy)
+        .insert(Speed(speed))
+        .insert(RigidBody::Dynamic)
+        .insert(SizeScale(size_scale))
+                
+        // Attach a collision shape
+        // .insert(CollisionShape::Cuboid {
+        //     half_extends: Vec3::new(size / 2., size / 2., 0.0),
+        //     border_radius: None,
+        // })
+        .insert(CollisionShape::Sphere {
+            radius: (size * size_scale) / 2.,
+        })
+
+        // // Optionally add other useful components...
+        .insert(Velocity::default())
+        // .insert(Velocity::from_linear(Vec3::X * 2.0))
+        // .insert(Acceleration::from_linear(Vec3::X * -1.0))
+        .insert(PhysicMaterial { friction: 1.0, density: 10.0 * size_scale, ..Default::default() })
+        //.insert(RotationConstraints::lock())
+        .insert(
+            CollisionLayers::new(Layer::Enemies, Layer::Player)
+                .with_mask(Layer::Enemies)
+                .with_mask(Layer::World),
+        )
+        .id()
+}
+
+fn show_chaser_count<'a>(chaser_count: &ChaserCount, texts: impl Iterator<Item = Mut<'a, Text>>) {
+    for mut text in texts {
+        text.sections[1].style.color = Color::Rgba {
+            red: 1.,
+            green: (255. - chaser_count.current as f32) / 255.,
+            blue: (255. - chaser_count.current as f32) / 255.,
+            alpha: 1.
+        };
+        text.sections[1].value = format!("{:.2}", chaser_count.current);
     }
 }
 
@@ -254,39 +556,39 @@ struct CenterMessageText;
 #[derive(Component)]
 struct SubCenterText;
 
+#[derive(Component)]
+struct DifficultyText;
+
+#[derive(Component)]
+struct HeartContainer;
+
 // A unit struct to help identify the color-changing Text component
 #[derive(Component)]
 struct ColorText;
 
-fn fullscreen_toggle(keyboard_input: Res<Input<KeyCode>>, mut windows: ResMut<Windows>) {
-    if keyboard_input.just_pressed(KeyCode::F11) {
-        let window = windows.get_primary_mut().unwrap();
-        
-        window.set_mode(
-            match window.mode() {
-                WindowMode::BorderlessFullscreen => WindowMode::Windowed,
-                _ => WindowMode::BorderlessFullscreen,
-            }
-        );
+// Goes through `Settings` so the choice is saved and applied by `apply_settings`
+fn fullscreen_toggle(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if settings.key_bindings.just_pressed(InputAction::Fullscreen, &keyboard_input) {
+        settings.display_mode =
+            match settings.display_mode {
+                DisplayMode::Windowed => DisplayMode::Borderless,
+                _ => DisplayMode::Windowed,
+            };
     }
 }
 
+// Reads the actions from `GameTick` rather than the keyboard, so replays can drive it
 fn player_movement(
-    game_paused: Res<GamePaused>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&Transform, &Speed, &mut Velocity), (With<Player>, Without<Camera2D>)>,
-    mut camera_query: Query<&mut Transform, (With<Camera2D>, Without<Player>)>,
+    tick: Res<GameTick>,
+    mut query: Query<(&PlayerSlot, &Speed, &mut Velocity), (With<Player>, Without<Downed>)>,
     player_died: Res<PlayerDied>,
     // mut touches: EventReader<TouchInput>,
     // windows: Res<Windows>,
 ) 
 {
-    if !game_paused.0 {
-        let (transform, Speed(speed), mut velocity) = query.single_mut();
-
-        camera_query.single_mut().translation = transform.translation;
-
-        if !player_died.0 {
+    if tick.running && !player_died.0 {
+        for (PlayerSlot(slot), Speed(speed), mut velocity) in query.iter_mut() {
+            let actions = tick.actions[*slot];
             let mut x = 0.0;
             let mut y = 0.0;
 
@@ -295,16 +597,16 @@ fn player_movement(
             //     x += (touch.position.x - window_width / 2.) / (window_width / 2.);
             //     y += (touch.position.y - window_width / 2.) / (window_width / 2.);
             // } else {
-            if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left) {
+            if actions.contains(InputAction::MoveLeft) {
                 x -= 1.0;
             };
-            if keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right) {
+            if actions.contains(InputAction::MoveRight) {
                 x += 1.0;
             };
-            if keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up) {
+            if actions.contains(InputAction::MoveUp) {
                 y += 1.0;
             };
-            if keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down) {
+            if actions.contains(InputAction::MoveDown) {
                 y -= 1.0;
             };
             // }
@@ -320,24 +622,47 @@ fn player_movement(
 
 fn move_chasing_enemies(
     game_paused: Res<GamePaused>,
-    mut query: Query<(&Transform, &Speed, &mut Velocity), With<ChasingEnemy>>,
-    player_query: Query<&Transform, (With<Player>, Without<ChasingEnemy>)>,
+    versus: Res<Versus>,
+    mut query: Query<(&Transform, &Speed, &mut Velocity, Option<&EnemyBehavior>), With<ChasingEnemy>>,
+    player_query: Query<(&PlayerSlot, &Transform), (With<Player>, Without<ChasingEnemy>, Without<Downed>)>,
 )
 {
     if !game_paused.0 {
-        if let Some(player_transform) = player_query.iter().next() {
-            for (transform, Speed(speed), mut velocity) in query.iter_mut() {
-                if transform.translation.x > player_transform.translation.x {
-                    velocity.linear.x -= speed;
-                } else {
-                    velocity.linear.x += speed;
-                }
+        let players: Vec<(usize, Vec3)> = player_query
+            .iter()
+            .map(|(PlayerSlot(slot), t)| (*slot, t.translation))
+            .collect();
+        // In versus every meteor goes after the last player to be pulsed at
+        let pulsed_at = versus.target.and_then(|target| players.iter().find(|(slot, _)| *slot == target));
+
+        for (transform, Speed(speed), mut velocity, behavior) in query.iter_mut() {
+            if behavior == Some(&EnemyBehavior::Drift) {
+                continue;
+            }
 
-                if transform.translation.y > player_transform.translation.y {
-                    velocity.linear.y -= speed;
-                } else {
-                    velocity.linear.y += speed;
-                }
+            // Otherwise each meteor goes after whichever living player is closest
+            let target = pulsed_at.or_else(|| {
+                players.iter().min_by(|a, b| {
+                    a.1.distance_squared(transform.translation)
+                        .partial_cmp(&b.1.distance_squared(transform.translation))
+                        .unwrap()
+                })
+            });
+            let target = match target {
+                Some((_, target)) => target,
+                None => break,
+            };
+
+            if transform.translation.x > target.x {
+                velocity.linear.x -= speed;
+            } else {
+                velocity.linear.x += speed;
+            }
+
+            if transform.translation.y > target.y {
+                velocity.linear.y -= speed;
+            } else {
+                velocity.linear.y += speed;
             }
         }
     }
@@ -352,52 +677,69 @@ fn calculate_health(
     mut heart_query: Query<(&mut UiImage, &HeartSprite), Without<PlayerHealth>>,
     full_heart_sprite: Res<FullHeartSprite>,
     empty_heart_sprite: Res<EmptyHeartSprite>,
-    mut health_query: Query<&mut PlayerHealth>,
+    mut health_query: Query<(&PlayerSlot, &mut PlayerHealth)>,
     mut center_text: Query<&mut Text, With<CenterMessageText>>,
-    mut sub_center_text: Query<&mut Text, (With<SubCenterText>, Without<CenterMessageText>)>,
     mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    difficulty: Res<Difficulty>,
+    mut hit_events: EventWriter<PlayerHit>,
+    god_mode: Res<GodMode>,
+    versus: Res<Versus>,
+    damage_query: Query<&Damage>,
 ) 
 {
     if !player_died.0 {
-        let mut health = health_query.single_mut();
+        let max_health = difficulty.settings().hearts;
 
         events
             .iter()
             .for_each(|event| {
+                let (layers_1, layers_2) = event.collision_layers();
+                let (entity_1, entity_2) = event.rigid_body_entities();
+                let (player, enemy) = if is_player(layers_1) && is_enemy(layers_2) {
+                    (entity_1, entity_2)
+                } else if is_player(layers_2) && is_enemy(layers_1) {
+                    (entity_2, entity_1)
+                } else {
+                    return;
+                };
+                // Bosses and fragments don't have a `Damage`, and hit for one heart
+                let damage = damage_query.get(enemy).map_or(1, |Damage(damage)| *damage);
 
-                if event.is_stopped() {
-                    let (layers_1, layers_2) = event.collision_layers();
-                    if health.0 < 5 {
-                        if is_player(layers_1) && is_enemy(layers_2) {
-                            health.0 += 1;
-                        } else if is_player(layers_2) && is_enemy(layers_1) {
-                            health.0 += 1;
-                        }
-                    }
+                let mut health = match health_query.get_mut(player) {
+                    // A player who is down stays down until the next run
+                    Ok((_, health)) if health.0 > 0 => health,
+                    _ => return,
+                };
+
+                if event.is_stopped() && health.0 < max_health {
+                    health.0 = health.0.saturating_add(damage).min(max_health);
                 }
-                if event.is_started() {
-                    if health.0 > 0 {
-                        let (layers_1, layers_2) = event.collision_layers();
-                        if is_player(layers_1) && is_enemy(layers_2) {
-                            health.0 -= 1;
-                        } else if is_player(layers_2) && is_enemy(layers_1) {
-                            health.0 -= 1;
-                        }
-                    }
+                if event.is_started() && !god_mode.0 {
+                    health.0 = health.0.saturating_sub(damage);
+                    hit_events.send(PlayerHit);
                 }
             });
 
-        if health.0 <= 0 {
+        // In co-op the run goes on as long as one player still has hearts, in versus
+        // it's over once there's only one left. See `versus::announce_winner`.
+        let living = health_query.iter().filter(|(_, health)| health.0 > 0).count();
+        let round_over = if versus.enabled { living <= 1 } else { living == 0 };
+        if round_over {
             player_died.0 = true;
-            center_text.single_mut().sections[0].value = String::from("You Died");
-            sub_center_text.single_mut().sections[0].value = String::from("Press R to restart");
+            for mut text in center_text.iter_mut() {
+                text.sections[0].value = String::from("You Died");
+            }
             enemy_spawn_timer.0.pause();
             for (mut sprite, _) in heart_query.iter_mut() {
                 sprite.0 = empty_heart_sprite.0.clone();
             }
         } else {
-            for (mut sprite, HeartSprite(id)) in heart_query.iter_mut() {
-                if health.0 > *id {
+            for (mut sprite, HeartSprite(slot, id)) in heart_query.iter_mut() {
+                let health = health_query
+                    .iter()
+                    .find(|(PlayerSlot(s), _)| s == slot)
+                    .map_or(0, |(_, health)| health.0);
+                if health > *id {
                     sprite.0 = full_heart_sprite.0.clone();
                 } else {
                     sprite.0 = empty_heart_sprite.0.clone();
@@ -407,6 +749,31 @@ fn calculate_health(
     }
 }
 
+// A co-op player who runs out of hearts drops out until the next run, or until the
+// console heals them. They stop colliding so the meteors all go after the other one.
+// The last player to go down stays where they are for the death screen.
+fn update_downed_players(
+    mut commands: Commands,
+    player_died: Res<PlayerDied>,
+    mut player_query: Query<
+        (Entity, &PlayerHealth, Option<&Downed>, &mut CollisionLayers, &mut Visibility, &mut Velocity),
+        With<Player>,
+    >,
+) {
+    for (entity, health, downed, mut layers, mut visibility, mut velocity) in player_query.iter_mut() {
+        if health.0 == 0 && downed.is_none() && !player_died.0 {
+            commands.entity(entity).insert(Downed);
+            *layers = CollisionLayers::none();
+            *velocity = Velocity::from_linear(Vec3::ZERO);
+            visibility.is_visible = false;
+        } else if health.0 > 0 && downed.is_some() {
+            commands.entity(entity).remove::<Downed>();
+            *layers = player_layers();
+            visibility.is_visible = true;
+        }
+    }
+}
+
 // Note: We check both layers each time to avoid a false-positive
 // that can occur if an entity has the default (unconfigured) `CollisionLayers`
 fn is_player(layers: CollisionLayers) -> bool {
@@ -417,10 +784,18 @@ fn is_enemy(layers: CollisionLayers) -> bool {
     !layers.contains_group(Layer::Player) && layers.contains_group(Layer::Enemies)
 }
 
+fn is_pickup(layers: CollisionLayers) -> bool {
+    layers.contains_group(Layer::Pickups) && !layers.contains_group(Layer::Player)
+}
+
 #[derive(Component)]
 struct Camera2D;
 
 struct GamePaused(bool);
+
+// How fast physics runs compared to real time
+const PHYSICS_TIME_SCALE: f32 = 1.5;
+
 struct ChaserCount {
     current: u32,
     max: u32,
@@ -444,9 +819,9 @@ fn increase_spawn_size(
     player_died: Res<PlayerDied>,
     game_paused: ResMut<GamePaused>,
     mut timer: ResMut<IncreaseSpawnSizeTimer>,
-    time: Res<Time>,
+    tick: Res<GameTick>,
 ) {
-    if !game_paused.0 && !player_died.0 && timer.0.tick(time.delta()).just_finished() {
+    if !game_paused.0 && !player_died.0 && timer.0.tick(tick.delta).just_finished() {
         if increments.0 < 100 {
             increments.0 += 1;
         }
@@ -459,16 +834,40 @@ struct FullHeartSprite(Handle<Image>);
 struct EmptyHeartSprite(Handle<Image>);
 struct ChaserSprite(Handle<Image>);
 struct RandomGenerator(rand::rngs::StdRng);
+// Everything random in a run, including the starfield, is derived from this seed
+struct RunSeed(u64);
+// Set to pick the seed of the next run instead of a random one
+#[derive(Default)]
+struct NextRunSeed(Option<u64>);
+// Meteors still collide but never take hearts away. Toggled from the console.
+#[derive(Default)]
+struct GodMode(bool);
 struct ChickenSprite(Handle<Image>);
+struct BoldFont(Handle<Font>);
+// Meteors spawn just outside this area around the players and are sized by its width.
+// Follows the window, except on the headless server which has none.
+#[derive(Default)]
+struct PlayArea(Vec2);
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    radar_settings: Res<RadarSettings>,
+) {
     // UI camera
     commands.spawn_bundle(UiCameraBundle::default());
     commands.spawn_bundle(OrthographicCameraBundle::new_2d()).insert(Camera2D);
     commands.insert_resource(SpawnTimer(Timer::from_seconds(0.5, true)));
     commands.insert_resource(IncreaseSpawnSizeTimer(Timer::from_seconds(5.0, true)));
-    commands.insert_resource(PhysicsTime::new(1.5));
-    commands.insert_resource(GamePaused(false));
+
+    // The game opens on the menu, so everything starts out paused
+    let mut physics_time = PhysicsTime::new(PHYSICS_TIME_SCALE);
+    physics_time.pause();
+    commands.insert_resource(physics_time);
+    commands.insert_resource(GamePaused(true));
+    commands.insert_resource(InMenu(true));
+    commands.insert_resource(SurvivalTime(0.0));
     commands.insert_resource(ChaserCount::new(0, 1000));
     commands.insert_resource(PlayerDied(false));
     commands.insert_resource(SpawnSizeIncrements(0));
@@ -507,6 +906,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     // Rich text with multiple sections
 
     let bold_font: Handle<Font> = asset_server.load("fonts/Fredoka/Fredoka-Bold.ttf");
+    commands.insert_resource(BoldFont(bold_font.clone()));
 
     let full_heart_sprite: Handle<Image> = asset_server.load("sprites/full_heart.png");
     let empty_heart_sprite: Handle<Image> = asset_server.load("sprites/empty_heart.png");
@@ -517,7 +917,9 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(FullHeartSprite(full_heart_sprite.clone()));
     commands.insert_resource(EmptyHeartSprite(empty_heart_sprite.clone()));
 
-    commands.insert_resource(RandomGenerator(rand::rngs::StdRng::from_entropy()));
+    let seed: u64 = rand::random();
+    commands.insert_resource(RunSeed(seed));
+    commands.insert_resource(RandomGenerator(rand::rngs::StdRng::seed_from_u64(seed)));
 
     commands
         .spawn_bundle(NodeBundle {
@@ -606,7 +1008,36 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ..Default::default()
                         })
                         .insert(EnemyCountText);
-                });                
+                });
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: Color::NONE.into(),
+                    style: Style {
+                        padding: Rect::all(Px(8.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }).with_children(|nested_parent| {
+                    nested_parent
+                        .spawn_bundle(TextBundle {
+                            text: Text {
+                                sections: vec![
+                                    TextSection {
+                                        value: "".to_string(),
+                                        style: TextStyle {
+                                            font: bold_font.clone(),
+                                            font_size: 48.0,
+                                            color: Color::WHITE,
+                                        },
+                                    },
+                                ],
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(DifficultyText);
+                });
         });
 
     commands
@@ -694,45 +1125,85 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     color: Color::NONE.into(),
                     ..Default::default()
                 })
+                .insert(HeartContainer)
                 .with_children(|nested_parent| {
                     nested_parent
                         .spawn_bundle(ImageBundle {
                             image: full_heart_sprite.clone().into(),
                             ..Default::default()
                         })
-                        .insert(HeartSprite(0));
+                        .insert(HeartSprite(0, 0));
                     
                     nested_parent
                         .spawn_bundle(ImageBundle {
                             image: full_heart_sprite.clone().into(),
                             ..Default::default()
                         })
-                        .insert(HeartSprite(1));
+                        .insert(HeartSprite(0, 1));
 
                     nested_parent
                         .spawn_bundle(ImageBundle {
                             image: full_heart_sprite.clone().into(),
                             ..Default::default()
                         })
-                        .insert(HeartSprite(2));
+                        .insert(HeartSprite(0, 2));
 
                     nested_parent
                         .spawn_bundle(ImageBundle {
                             image: full_heart_sprite.clone().into(),
                             ..Default::default()
                         })
-                        .insert(HeartSprite(3));
+                        .insert(HeartSprite(0, 3));
 
                     nested_parent
                         .spawn_bundle(ImageBundle {
                             image: full_heart_sprite.clone().into(),
                             ..Default::default()
                         })
-                        .insert(HeartSprite(4));
+                        .insert(HeartSprite(0, 4));
                 });
             
         });
 
+    radar::spawn_radar(&mut commands, &mut images, &radar_settings);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    right: Val::Px(15.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "FPS: ".to_string(),
+                        style: TextStyle {
+                            font: bold_font.clone(),
+                            font_size: 32.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font: bold_font.clone(),
+                            font_size: 32.0,
+                            color: Color::GOLD,
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(FpsText);
+
     // commands
     //     .spawn_bundle(
     //         ImageBundle {
@@ -760,66 +1231,229 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 }
 
 
-// Need to add timers to this as they are added to the game.
-// Also important. Need to check GamePaused flag in other systems before applying changes.
 fn toggle_physics_pause(
     input: Res<Input<KeyCode>>,
+    game_paused: Res<GamePaused>,
+    mut pause_events: EventWriter<SetPaused>,
+    settings: Res<Settings>,
+) {
+    if settings.key_bindings.just_pressed(InputAction::Pause, &input) {
+        pause_events.send(SetPaused(!game_paused.0));
+    }
+}
+
+// Alt-tabbing out or switching browser tabs shouldn't get the player killed
+fn pause_on_focus_loss(
+    mut focus_events: EventReader<WindowFocused>,
+    mut pause_events: EventWriter<SetPaused>,
+) {
+    if focus_events.iter().any(|e| !e.focused) {
+        pause_events.send(SetPaused(true));
+    }
+}
+
+// Need to add timers to this as they are added to the game.
+// Also important. Need to check GamePaused flag in other systems before applying changes.
+// Runs before `Update`, so a frame is either paused or not the whole way through.
+fn apply_pause(
+    mut pause_events: EventReader<SetPaused>,
     mut physics_time: ResMut<PhysicsTime>,
     mut game_paused: ResMut<GamePaused>,
     mut enemy_spawn_timer: ResMut<SpawnTimer>,
-    mut center_text: Query<&mut Text, With<CenterMessageText>>,
+    mut size_timer: ResMut<IncreaseSpawnSizeTimer>,
     player_died: Res<PlayerDied>,
+    in_menu: Res<InMenu>,
 ) {
-    if !player_died.0 && input.just_pressed(KeyCode::Space) {
-        if game_paused.0 {
-            physics_time.resume();
-            enemy_spawn_timer.0.unpause();
-            game_paused.0 = false;
-            center_text.single_mut().sections[0].value = "".to_string();
-            
-        } else {
+    for SetPaused(paused) in pause_events.iter() {
+        if player_died.0 || in_menu.0 || *paused == game_paused.0 {
+            continue;
+        }
+
+        if *paused {
             physics_time.pause();
             enemy_spawn_timer.0.pause();
-            game_paused.0 = true;
-            center_text.single_mut().sections[0].value = "Paused".to_string();
+            size_timer.0.pause();
+        } else {
+            physics_time.resume();
+            enemy_spawn_timer.0.unpause();
+            size_timer.0.unpause();
         }
+        game_paused.0 = *paused;
+    }
+}
+
+fn restart_input(
+    input: Res<Input<KeyCode>>,
+    player_died: Res<PlayerDied>,
+    in_menu: Res<InMenu>,
+    mut restart_events: EventWriter<RestartRun>,
+    settings: Res<Settings>,
+) {
+    if player_died.0 && !in_menu.0 && settings.key_bindings.just_pressed(InputAction::Restart, &input) {
+        restart_events.send(RestartRun);
     }
 }
 
 fn reset_game(
     mut commands: Commands,
-    input: Res<Input<KeyCode>>,
+    mut restart_events: EventReader<RestartRun>,
     mut physics_time: ResMut<PhysicsTime>,
     mut game_paused: ResMut<GamePaused>,
-    mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    (mut enemy_spawn_timer, mut size_timer, mut size_increments): (ResMut<SpawnTimer>, ResMut<IncreaseSpawnSizeTimer>, ResMut<SpawnSizeIncrements>),
     mut center_text: Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>, Without<EnemyCountText>)>,
     mut sub_center_text: Query<&mut Text, (With<SubCenterText>, Without<CenterMessageText>, Without<EnemyCountText>)>,
     mut player_died: ResMut<PlayerDied>,
-    mut health_query: Query<&mut PlayerHealth>,
     chaser_query: Query<Entity, With<ChasingEnemy>>,
-    mut player_query: Query<(&mut Transform, &mut Velocity), With<Player>>,
     mut chaser_count: ResMut<ChaserCount>,
-    mut enemy_count_text_query: Query<&mut Text, (With<EnemyCountText>, Without<CenterMessageText>, Without<SubCenterText>)>
+    mut enemy_count_text_query: Query<&mut Text, (With<EnemyCountText>, Without<CenterMessageText>, Without<SubCenterText>)>,
+    difficulty: Res<Difficulty>,
+    mut survival_time: ResMut<SurvivalTime>,
 ) {
-    if player_died.0 && input.just_pressed(KeyCode::R) {
+    if restart_events.iter().count() > 0 {
+        let settings = difficulty.settings();
+
         chaser_query.iter().for_each(|e| commands.entity(e).despawn());
         chaser_count.current = 0;
-        enemy_count_text_query.single_mut().sections[1].value = String::from("0");
-        let (mut transform, mut velocity) = player_query.single_mut();
-        *transform = Transform::from_xyz(0.0, 0.0, 0.0);
-        *velocity = Velocity::from_linear(Vec3::new(0.0, 0.0, 0.0));
+        for mut text in enemy_count_text_query.iter_mut() {
+            text.sections[1].value = String::from("0");
+        }
         physics_time.resume();
-        enemy_spawn_timer.0.reset();
-        enemy_spawn_timer.0 = Timer::from_seconds(0.5, true);
-        enemy_spawn_timer.0.unpause();
+        enemy_spawn_timer.0 = Timer::from_seconds(settings.spawn_interval, true);
+        size_timer.0 = Timer::from_seconds(settings.size_increase_interval, true);
+        size_increments.0 = 0;
+        survival_time.0 = 0.0;
         game_paused.0 = false;
-        center_text.single_mut().sections[0].value = String::from("");
-        sub_center_text.single_mut().sections[0].value = String::from("");
-        health_query.single_mut().0 = 5;
+        for mut text in center_text.iter_mut().chain(sub_center_text.iter_mut()) {
+            text.sections[0].value = String::from("");
+        }
         player_died.0 = false;
     }
 }
 
+// Puts every player back at the start with full hearts, and adds or removes player two
+// if the player count changed in the menu. Downed players come back in `update_downed_players`.
+fn reset_players(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartRun>,
+    player_count: Res<PlayerCount>,
+    difficulty: Res<Difficulty>,
+    windows: Res<Windows>,
+    asset_server: Res<AssetServer>,
+    mut player_query: Query<(Entity, &PlayerSlot, &mut Transform, &mut Velocity, &mut PlayerHealth)>,
+) {
+    if restart_events.iter().count() > 0 {
+        let hearts = difficulty.settings().hearts;
+        let window_width = windows.get_primary().unwrap().width();
+        let mut present = [false; coop::MAX_PLAYERS];
+
+        for (entity, PlayerSlot(slot), mut transform, mut velocity, mut health) in player_query.iter_mut() {
+            if *slot >= player_count.0 {
+                commands.entity(entity).despawn();
+                continue;
+            }
+
+            present[*slot] = true;
+            *transform = Transform::from_translation(coop::start_position(*slot, player_count.0, window_width));
+            *velocity = Velocity::from_linear(Vec3::new(0.0, 0.0, 0.0));
+            health.0 = hearts;
+        }
+
+        for slot in (0..player_count.0).filter(|slot| !present[*slot]) {
+            spawn_player(
+                &mut commands,
+                asset_server.load("sprites/PlayerEarth.png"),
+                slot,
+                player_count.0,
+                window_width,
+                hearts,
+            );
+        }
+    }
+}
+
+fn track_play_area(windows: Res<Windows>, mut play_area: ResMut<PlayArea>) {
+    if let Some(window) = windows.get_primary() {
+        let size = Vec2::new(window.width(), window.height());
+        if play_area.0 != size {
+            play_area.0 = size;
+        }
+    }
+}
+
+// Every run gets a fresh seed so that the spawn sequence only depends on the seed.
+// Runs before `Update` so the whole first frame of a run already uses the new seed.
+fn reseed_random_generator(
+    mut restart_events: EventReader<RestartRun>,
+    mut run_seed: ResMut<RunSeed>,
+    mut next_seed: ResMut<NextRunSeed>,
+    mut random_gen: ResMut<RandomGenerator>,
+    daily: Res<DailyChallenge>,
+) {
+    if restart_events.iter().count() > 0 {
+        run_seed.0 = next_seed.0.take().or_else(|| daily.seed()).unwrap_or_else(rand::random);
+        random_gen.0 = rand::rngs::StdRng::seed_from_u64(run_seed.0);
+    }
+}
+
+// Difficulties have different heart counts and co-op adds a second row, so the hearts
+// are rebuilt at the start of every run
+fn rebuild_hearts(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartRun>,
+    container_query: Query<(Entity, Option<&Children>), With<HeartContainer>>,
+    full_heart_sprite: Res<FullHeartSprite>,
+    difficulty: Res<Difficulty>,
+    player_count: Res<PlayerCount>,
+) {
+    if restart_events.iter().count() > 0 {
+        let (container, children) = container_query.single();
+        if let Some(children) = children {
+            children.iter().for_each(|e| commands.entity(*e).despawn_recursive());
+        }
+
+        let rows: Vec<Entity> = (0..player_count.0)
+            .map(|slot| {
+                commands
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            margin: Rect {
+                                left: Px(24.0),
+                                right: Px(24.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        for id in 0..difficulty.settings().hearts {
+                            row
+                                .spawn_bundle(ImageBundle {
+                                    image: full_heart_sprite.0.clone().into(),
+                                    color: PLAYER_COLORS[slot].into(),
+                                    ..Default::default()
+                                })
+                                .insert(HeartSprite(slot, id));
+                        }
+                    })
+                    .id()
+            })
+            .collect();
+
+        commands.entity(container).push_children(&rows);
+    }
+}
+
+fn update_difficulty_text(difficulty: Res<Difficulty>, mut query: Query<&mut Text, With<DifficultyText>>) {
+    if difficulty.is_changed() {
+        for mut text in query.iter_mut() {
+            text.sections[0].value = difficulty.name().to_string();
+            text.sections[0].style.color = difficulty.color();
+        }
+    }
+}
+
 fn text_update_system(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text, With<FpsText>>) {
     for mut text in query.iter_mut() {
         if let Some(fps) = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS) {
@@ -829,4 +1463,11 @@ fn text_update_system(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text,
             }
         }
     }
-}
\ No newline at end of file
+}
+fn toggle_fps_text(settings: Res<Settings>, mut query: Query<&mut Visibility, With<FpsText>>) {
+    if settings.is_changed() {
+        for mut visibility in query.iter_mut() {
+            visibility.is_visible = settings.show_fps;
+        }
+    }
+}
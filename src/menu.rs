@@ -0,0 +1,308 @@
+use bevy::prelude::*;
+
+use crate::{
+    arena::{Arena, ArenaShape},
+    boss::Bosses,
+    chickens::ChickenSettings,
+    coop::PlayerCount,
+    daily::{DailyChallenge, DailyScores},
+    date::Date,
+    difficulty::Difficulty,
+    gravity::Gravity,
+    hazards::Hazards,
+    high_scores::HighScores,
+    pause_menu::OpenSettings,
+    replay_browser::OpenReplays,
+    versus::Versus,
+    BoldFont, CenterMessageText, PlayerDied, RestartRun, SubCenterText,
+};
+
+// While the menu is open the game stays paused. The menu is spawned and despawned
+// by `sync_menu` whenever this flag changes.
+pub struct InMenu(pub bool);
+
+#[derive(Component)]
+pub struct MenuRoot;
+
+#[derive(Component)]
+pub struct MenuDifficultyText;
+
+#[derive(Component)]
+pub struct MenuHighScoreText;
+
+pub fn sync_menu(
+    mut commands: Commands,
+    in_menu: Res<InMenu>,
+    menu_query: Query<Entity, With<MenuRoot>>,
+    bold_font: Res<BoldFont>,
+) {
+    let menu_spawned = menu_query.iter().next().is_some();
+
+    if in_menu.0 && !menu_spawned {
+        spawn_menu(&mut commands, bold_font.0.clone());
+    } else if !in_menu.0 && menu_spawned {
+        menu_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    }
+}
+
+fn spawn_menu(commands: &mut Commands, font: Handle<Font>) {
+    let centered = TextAlignment {
+        horizontal: HorizontalAlign::Center,
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+            ..Default::default()
+        })
+        .insert(MenuRoot)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Earth Escape",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 82.0,
+                        color: Color::WHITE,
+                    },
+                    centered,
+                ),
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "< ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 48.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 48.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: " >".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 48.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 24.0,
+                                    color: Color::GRAY,
+                                },
+                            },
+                        ],
+                        alignment: centered,
+                    },
+                    ..Default::default()
+                })
+                .insert(MenuDifficultyText);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 28.0,
+                            color: Color::GOLD,
+                        },
+                        centered,
+                    ),
+                    ..Default::default()
+                })
+                .insert(MenuHighScoreText);
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Left/Right or 1-4 to pick a difficulty, P for co-op or versus, O for orbital gravity, B for an arena\nPress Enter to start or C for the daily challenge\nS for settings, R for replays",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 36.0,
+                        color: Color::GREEN,
+                    },
+                    centered,
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+pub fn menu_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut in_menu: ResMut<InMenu>,
+    mut difficulty: ResMut<Difficulty>,
+    mut player_count: ResMut<PlayerCount>,
+    mut versus: ResMut<Versus>,
+    mut gravity: ResMut<Gravity>,
+    mut arena: ResMut<Arena>,
+    // Only changed from the console, but the daily challenge puts them back
+    (mut bosses, mut chicken_settings, mut hazards): (ResMut<Bosses>, ResMut<ChickenSettings>, ResMut<Hazards>),
+    mut restart_events: EventWriter<RestartRun>,
+    mut settings_events: EventWriter<OpenSettings>,
+    mut replay_events: EventWriter<OpenReplays>,
+    mut daily: ResMut<DailyChallenge>,
+    player_died: Res<PlayerDied>,
+    mut center_text: Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>)>,
+    mut sub_center_text: Query<&mut Text, (With<SubCenterText>, Without<CenterMessageText>)>,
+) {
+    if !in_menu.0 {
+        // The death screen is the only place the menu can be reopened from
+        if player_died.0 && keyboard_input.just_pressed(KeyCode::M) {
+            in_menu.0 = true;
+            center_text.single_mut().sections[0].value = String::from("");
+            sub_center_text.single_mut().sections[0].value = String::from("");
+        }
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Left) || keyboard_input.just_pressed(KeyCode::A) {
+        *difficulty = difficulty.previous();
+    }
+    if keyboard_input.just_pressed(KeyCode::Right) || keyboard_input.just_pressed(KeyCode::D) {
+        *difficulty = difficulty.next();
+    }
+
+    let number_keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+    for (key, choice) in number_keys.iter().zip(Difficulty::ALL.iter()) {
+        if keyboard_input.just_pressed(*key) {
+            *difficulty = *choice;
+        }
+    }
+
+    // Single player, then co-op, then versus
+    if keyboard_input.just_pressed(KeyCode::P) {
+        match (player_count.0, versus.enabled) {
+            (1, _) => player_count.0 = 2,
+            (_, false) => versus.enabled = true,
+            _ => {
+                player_count.0 = 1;
+                versus.enabled = false;
+            }
+        }
+        versus.wins = Default::default();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::O) {
+        gravity.enabled = !gravity.enabled;
+    }
+
+    // Open space, then a rectangular arena, then a circular one
+    if keyboard_input.just_pressed(KeyCode::B) {
+        arena.shape = match arena.shape {
+            None => Some(ArenaShape::Rectangle),
+            Some(ArenaShape::Rectangle) => Some(ArenaShape::Circle),
+            Some(ArenaShape::Circle) => None,
+        };
+    }
+
+    if keyboard_input.just_pressed(KeyCode::S) {
+        settings_events.send(OpenSettings);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::R) {
+        replay_events.send(OpenReplays);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        in_menu.0 = false;
+        restart_events.send(RestartRun);
+    }
+
+    // Everyone plays the daily challenge alone, on the same difficulty and in open space,
+    // with whatever the console may have changed put back how it started
+    if keyboard_input.just_pressed(KeyCode::C) {
+        daily.date = Some(Date::today());
+        *difficulty = Difficulty::Normal;
+        player_count.0 = 1;
+        versus.enabled = false;
+        gravity.enabled = false;
+        arena.shape = None;
+        bosses.enabled = true;
+        *chicken_settings = ChickenSettings::default();
+        hazards.enabled = true;
+        in_menu.0 = false;
+        restart_events.send(RestartRun);
+    }
+}
+
+pub fn update_menu_text(
+    in_menu: Res<InMenu>,
+    difficulty: Res<Difficulty>,
+    player_count: Res<PlayerCount>,
+    versus: Res<Versus>,
+    gravity: Res<Gravity>,
+    arena: Res<Arena>,
+    high_scores: Res<HighScores>,
+    daily_scores: Res<DailyScores>,
+    mut difficulty_text: Query<&mut Text, (With<MenuDifficultyText>, Without<MenuHighScoreText>)>,
+    mut high_score_text: Query<&mut Text, (With<MenuHighScoreText>, Without<MenuDifficultyText>)>,
+) {
+    if !in_menu.0 {
+        return;
+    }
+
+    let settings = difficulty.settings();
+
+    for mut text in difficulty_text.iter_mut() {
+        text.sections[1].value = difficulty.name().to_string();
+        text.sections[1].style.color = difficulty.color();
+        let players = if versus.enabled {
+            "Versus: player one on WASD and Left Shift, player two on the arrows and Right Shift\nor gamepads 1 and 2 with A to repulse. Last Earth standing wins"
+        } else if player_count.0 > 1 {
+            "Co-op: player one on WASD or gamepad 1, player two on the arrows or gamepad 2"
+        } else {
+            "Single player"
+        };
+        let gravity = if gravity.enabled {
+            "Orbital gravity: suns and planets pull on everything, fly past them to slingshot"
+        } else {
+            "No gravity"
+        };
+        let bounds = match arena.shape {
+            Some(shape) => format!("A {} arena {} screens across", shape.name(), arena.size),
+            None => String::from("Open space"),
+        };
+        text.sections[3].value = format!(
+            "\n{} hearts, enemy speed {:.1}, a new enemy every {:.2}s\n{}\n{}, {}",
+            settings.hearts, settings.chaser_speed, settings.spawn_interval, players, gravity, bounds,
+        );
+    }
+
+    for mut text in high_score_text.iter_mut() {
+        let daily_best = match daily_scores.best(Date::today()) {
+            Some(entry) => format!("{:.1}s", entry.seconds),
+            None => String::from("not played yet"),
+        };
+        text.sections[0].value = format!(
+            "\nBest runs on {}\n{}\n\nToday's daily challenge: {}\n",
+            difficulty.name(),
+            high_scores.format_table(*difficulty),
+            daily_best,
+        );
+    }
+}
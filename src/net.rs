@@ -0,0 +1,349 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+use crate::{controls::ActionSet, difficulty::Difficulty, replay::ByteReader};
+
+// Network play has an authoritative server that runs the whole simulation, meteors
+// included, and clients that only send what their player is pressing and draw the
+// snapshots they get back. Everything goes over UDP as small hand-packed messages,
+// and a lost message is never resent since the next input or snapshot replaces it.
+// Snapshots with lots of chasers go out in several parts, each small enough to cross
+// the internet without being fragmented, and the client puts them back together.
+//
+//   earth_escape --server [port]       runs the headless server, no window
+//   earth_escape --connect <address>   joins a server, e.g. --connect 127.0.0.1:7878
+
+pub const DEFAULT_PORT: u16 = 7878;
+// The server simulates at this rate and sends a snapshot every `SNAPSHOT_INTERVAL` ticks
+pub const SERVER_TICK_RATE: f64 = 60.;
+pub const SNAPSHOT_INTERVAL: u32 = 3;
+// A client or server that hasn't been heard from for this long has gone away
+pub const TIMEOUT_SECONDS: f64 = 5.;
+// Comfortably under the usual internet MTU once IP and UDP headers are added
+pub const MAX_MESSAGE_SIZE: usize = 1200;
+// 21 bytes each, which leaves room for the snapshot header and every player
+const CHASERS_PER_PART: usize = 50;
+
+const MAGIC: &[u8; 4] = b"EENT";
+const VERSION: u8 = 4;
+
+// Lets the single player systems step aside when a server is running the game instead
+pub enum NetMode {
+    Offline,
+    Client,
+}
+
+pub fn offline(mode: Res<NetMode>) -> ShouldRun {
+    match *mode {
+        NetMode::Offline => ShouldRun::Yes,
+        NetMode::Client => ShouldRun::No,
+    }
+}
+
+pub enum ClientMessage {
+    // Sent until the server answers with `Welcome` or `Full`
+    Hello,
+    // Sent every frame, which also keeps the connection alive
+    Input(ActionSet),
+    Bye,
+}
+
+pub enum ServerMessage {
+    Welcome { slot: u8, difficulty: Difficulty },
+    Full,
+    // Part `part` of `parts`, every part has all the players and its own share of the chasers
+    SnapshotPart { part: u8, parts: u8, snapshot: Snapshot },
+}
+
+#[derive(Clone)]
+pub struct Snapshot {
+    pub tick: u32,
+    // Goes up every time the server starts a new round
+    pub round: u32,
+    pub survival_time: f32,
+    pub players: Vec<PlayerState>,
+    pub chasers: Vec<ChaserState>,
+}
+
+impl Snapshot {
+    // Seconds since the server started, which is what snapshots are interpolated by
+    pub fn time(&self) -> f64 {
+        self.tick as f64 / SERVER_TICK_RATE
+    }
+
+    // Always at least one part, so the players still go out when there are no chasers
+    pub fn parts(&self) -> Vec<ServerMessage> {
+        let chunks: Vec<&[ChaserState]> = self.chasers.chunks(CHASERS_PER_PART).take(u8::MAX as usize).collect();
+        let parts = chunks.len().max(1) as u8;
+        (0..parts)
+            .map(|part| ServerMessage::SnapshotPart {
+                part,
+                parts,
+                snapshot: Snapshot {
+                    tick: self.tick,
+                    round: self.round,
+                    survival_time: self.survival_time,
+                    players: self.players.clone(),
+                    chasers: chunks.get(part as usize).map_or(Vec::new(), |chunk| chunk.to_vec()),
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PlayerState {
+    pub slot: u8,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub health: u8,
+}
+
+#[derive(Clone, Copy)]
+pub struct ChaserState {
+    // Stays the same for the chaser's whole life, so clients can match it up between snapshots.
+    // The server's whole entity, generation included, so a reused index reads as a new chaser.
+    pub id: u64,
+    pub position: Vec2,
+    pub size_scale: f32,
+    pub chicken: bool,
+}
+
+impl ClientMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = header();
+        match self {
+            ClientMessage::Hello => bytes.push(0),
+            ClientMessage::Input(actions) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&actions.0.to_le_bytes());
+            }
+            ClientMessage::Bye => bytes.push(2),
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<ClientMessage> {
+        let mut reader = read_header(bytes)?;
+        match reader.take(1)?[0] {
+            0 => Some(ClientMessage::Hello),
+            1 => Some(ClientMessage::Input(ActionSet(u16::from_le_bytes(reader.array()?)))),
+            2 => Some(ClientMessage::Bye),
+            _ => None,
+        }
+    }
+}
+
+impl ServerMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = header();
+        match self {
+            ServerMessage::Welcome { slot, difficulty } => {
+                bytes.extend_from_slice(&[0, *slot]);
+                bytes.push(Difficulty::ALL.iter().position(|d| d == difficulty).unwrap() as u8);
+            }
+            ServerMessage::Full => bytes.push(1),
+            ServerMessage::SnapshotPart { part, parts, snapshot } => {
+                bytes.extend_from_slice(&[2, *part, *parts]);
+                bytes.extend_from_slice(&snapshot.tick.to_le_bytes());
+                bytes.extend_from_slice(&snapshot.round.to_le_bytes());
+                bytes.extend_from_slice(&snapshot.survival_time.to_le_bytes());
+                bytes.push(snapshot.players.len() as u8);
+                for player in snapshot.players.iter() {
+                    bytes.push(player.slot);
+                    write_vec2(&mut bytes, player.position);
+                    write_vec2(&mut bytes, player.velocity);
+                    bytes.push(player.health);
+                }
+                bytes.extend_from_slice(&(snapshot.chasers.len() as u32).to_le_bytes());
+                for chaser in snapshot.chasers.iter() {
+                    bytes.extend_from_slice(&chaser.id.to_le_bytes());
+                    write_vec2(&mut bytes, chaser.position);
+                    bytes.extend_from_slice(&chaser.size_scale.to_le_bytes());
+                    bytes.push(chaser.chicken as u8);
+                }
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<ServerMessage> {
+        let mut reader = read_header(bytes)?;
+        match reader.take(1)?[0] {
+            0 => {
+                let slot = reader.take(1)?[0];
+                let difficulty = *Difficulty::ALL.get(reader.take(1)?[0] as usize)?;
+                Some(ServerMessage::Welcome { slot, difficulty })
+            }
+            1 => Some(ServerMessage::Full),
+            2 => {
+                let part = reader.take(1)?[0];
+                let parts = reader.take(1)?[0];
+                if part >= parts {
+                    return None;
+                }
+                let tick = u32::from_le_bytes(reader.array()?);
+                let round = u32::from_le_bytes(reader.array()?);
+                let survival_time = f32::from_le_bytes(reader.array()?);
+
+                let player_count = reader.take(1)?[0] as usize;
+                let mut players = Vec::with_capacity(player_count);
+                for _ in 0..player_count {
+                    players.push(PlayerState {
+                        slot: reader.take(1)?[0],
+                        position: read_vec2(&mut reader)?,
+                        velocity: read_vec2(&mut reader)?,
+                        health: reader.take(1)?[0],
+                    });
+                }
+
+                let chaser_count = u32::from_le_bytes(reader.array()?) as usize;
+                let mut chasers = Vec::with_capacity(chaser_count.min(bytes.len() / 21));
+                for _ in 0..chaser_count {
+                    chasers.push(ChaserState {
+                        id: u64::from_le_bytes(reader.array()?),
+                        position: read_vec2(&mut reader)?,
+                        size_scale: f32::from_le_bytes(reader.array()?),
+                        chicken: reader.take(1)?[0] != 0,
+                    });
+                }
+
+                Some(ServerMessage::SnapshotPart {
+                    part,
+                    parts,
+                    snapshot: Snapshot {
+                        tick,
+                        round,
+                        survival_time,
+                        players,
+                        chasers,
+                    },
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn header() -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(64);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes
+}
+
+// Anything from another program or another version of the game is dropped here
+fn read_header(bytes: &[u8]) -> Option<ByteReader> {
+    let mut reader = ByteReader(bytes);
+    if reader.take(4)? != MAGIC || reader.take(1)?[0] != VERSION {
+        return None;
+    }
+    Some(reader)
+}
+
+fn write_vec2(bytes: &mut Vec<u8>, value: Vec2) {
+    bytes.extend_from_slice(&value.x.to_le_bytes());
+    bytes.extend_from_slice(&value.y.to_le_bytes());
+}
+
+fn read_vec2(reader: &mut ByteReader) -> Option<Vec2> {
+    Some(Vec2::new(f32::from_le_bytes(reader.array()?), f32::from_le_bytes(reader.array()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(chasers: usize) -> Snapshot {
+        Snapshot {
+            tick: 600,
+            round: 3,
+            survival_time: 12.5,
+            players: vec![
+                PlayerState { slot: 0, position: Vec2::new(1., 2.), velocity: Vec2::new(-3., 4.), health: 3 },
+                PlayerState { slot: 2, position: Vec2::new(-5., 6.), velocity: Vec2::ZERO, health: 0 },
+            ],
+            chasers: (0..chasers)
+                .map(|i| ChaserState {
+                    id: ((i as u64) << 32) | 7,
+                    position: Vec2::new(i as f32, -(i as f32)),
+                    size_scale: 1. + i as f32 / 10.,
+                    chicken: i % 5 == 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        assert!(matches!(ClientMessage::from_bytes(&ClientMessage::Hello.to_bytes()), Some(ClientMessage::Hello)));
+        assert!(matches!(ClientMessage::from_bytes(&ClientMessage::Bye.to_bytes()), Some(ClientMessage::Bye)));
+        match ClientMessage::from_bytes(&ClientMessage::Input(ActionSet(0x155)).to_bytes()) {
+            Some(ClientMessage::Input(actions)) => assert_eq!(actions, ActionSet(0x155)),
+            _ => panic!("expected input"),
+        }
+    }
+
+    #[test]
+    fn welcome_round_trips() {
+        let bytes = ServerMessage::Welcome { slot: 3, difficulty: Difficulty::Insane }.to_bytes();
+        match ServerMessage::from_bytes(&bytes) {
+            Some(ServerMessage::Welcome { slot, difficulty }) => {
+                assert_eq!(slot, 3);
+                assert_eq!(difficulty, Difficulty::Insane);
+            }
+            _ => panic!("expected welcome"),
+        }
+        assert!(matches!(ServerMessage::from_bytes(&ServerMessage::Full.to_bytes()), Some(ServerMessage::Full)));
+    }
+
+    #[test]
+    fn snapshot_parts_fit_and_round_trip() {
+        let original = snapshot(CHASERS_PER_PART * 2 + 7);
+        let parts = original.parts();
+        assert_eq!(parts.len(), 3);
+
+        let mut chasers = Vec::new();
+        for (index, message) in parts.iter().enumerate() {
+            let bytes = message.to_bytes();
+            assert!(bytes.len() <= MAX_MESSAGE_SIZE, "part {} is {} bytes", index, bytes.len());
+
+            match ServerMessage::from_bytes(&bytes) {
+                Some(ServerMessage::SnapshotPart { part, parts, snapshot }) => {
+                    assert_eq!((part as usize, parts), (index, 3));
+                    assert_eq!((snapshot.tick, snapshot.round), (original.tick, original.round));
+                    assert_eq!(snapshot.survival_time, original.survival_time);
+                    assert_eq!(snapshot.players.len(), original.players.len());
+                    for (read, sent) in snapshot.players.iter().zip(original.players.iter()) {
+                        assert_eq!((read.slot, read.health), (sent.slot, sent.health));
+                        assert_eq!((read.position, read.velocity), (sent.position, sent.velocity));
+                    }
+                    chasers.extend(snapshot.chasers);
+                }
+                _ => panic!("expected a snapshot part"),
+            }
+        }
+
+        assert_eq!(chasers.len(), original.chasers.len());
+        for (read, sent) in chasers.iter().zip(original.chasers.iter()) {
+            assert_eq!((read.id, read.position, read.size_scale), (sent.id, sent.position, sent.size_scale));
+            assert_eq!(read.chicken, sent.chicken);
+        }
+    }
+
+    #[test]
+    fn empty_snapshot_still_sends_players() {
+        let parts = snapshot(0).parts();
+        assert_eq!(parts.len(), 1);
+        match ServerMessage::from_bytes(&parts[0].to_bytes()) {
+            Some(ServerMessage::SnapshotPart { snapshot, .. }) => assert_eq!(snapshot.players.len(), 2),
+            _ => panic!("expected a snapshot part"),
+        }
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = ClientMessage::Hello.to_bytes();
+        bytes[4] = VERSION + 1;
+        assert!(ClientMessage::from_bytes(&bytes).is_none());
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::{
+    coop::{PlayerCount, PLAYER_COLORS},
+    difficulty::Difficulty,
+    high_scores::SurvivalTime,
+    menu::InMenu,
+    net::{ClientMessage, NetMode, PlayerState, ServerMessage, Snapshot, MAX_MESSAGE_SIZE, TIMEOUT_SECONDS},
+    replay::GameTick,
+    CenterMessageText, ChaserCount, ChaserSprite, ChasingEnemy, Chicken, ChickenSprite, EnemyCountText, PlayArea,
+    Player, PlayerDied, PlayerHealth, RestartRun, SizeScale, SubCenterText,
+};
+
+// A client draws the server's world instead of simulating its own. Other players and
+// every chaser are shown this far in the past and blended between the two snapshots
+// around that time, which hides the gaps between snapshots and most network jitter.
+// The local Earth is still moved by the local physics so steering feels immediate, and
+// is put back where the server has it whenever a new snapshot comes in.
+const INTERPOLATION_DELAY: f64 = 0.1;
+// About a second of snapshots, far more than interpolation ever needs
+const MAX_BUFFERED_SNAPSHOTS: usize = 20;
+const HELLO_INTERVAL: f64 = 1.;
+
+pub struct NetClientPlugin {
+    pub address: String,
+}
+
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut App) {
+        let client = match NetClient::connect(&self.address) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Could not connect to {}: {}", self.address, e);
+                return;
+            }
+        };
+
+        app
+            .insert_resource(client)
+            .insert_resource(NetMode::Client)
+            .init_resource::<RemoteEntities>()
+            .add_startup_system_to_stage(StartupStage::PostStartup, show_connecting)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                receive_server_messages
+                    .label("receive_server_messages")
+                    .before("reseed_random_generator"),
+            )
+            .add_system_to_stage(CoreStage::PreUpdate, send_input.after("advance_tick"))
+            .add_system(apply_snapshots.before("calculate_health"))
+            .add_system(tint_own_earth)
+            .add_system(show_spectating)
+            .add_system_to_stage(CoreStage::Last, say_goodbye);
+    }
+}
+
+pub struct NetClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    slot: Option<u8>,
+    // Oldest first
+    snapshots: VecDeque<Snapshot>,
+    // The snapshot whose parts are still coming in, and which of them have
+    assembling: Option<(Snapshot, Vec<bool>)>,
+    // Server time minus local time, smoothed over many snapshots
+    clock_offset: Option<f64>,
+    round: Option<u32>,
+    // The local player as of the newest snapshot, until it's been applied
+    correction: Option<PlayerState>,
+    last_heard: f64,
+    last_hello: Option<f64>,
+    lost: bool,
+}
+
+impl NetClient {
+    fn connect(address: &str) -> std::io::Result<Self> {
+        let server = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "no address found"))?;
+        let socket = UdpSocket::bind(if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.set_nonblocking(true)?;
+
+        Ok(NetClient {
+            socket,
+            server,
+            slot: None,
+            snapshots: VecDeque::new(),
+            assembling: None,
+            clock_offset: None,
+            round: None,
+            correction: None,
+            last_heard: 0.,
+            last_hello: None,
+            lost: false,
+        })
+    }
+
+    // Hands back the whole snapshot once its last part is in. A snapshot missing a part
+    // is dropped as soon as a part of a newer one turns up.
+    fn assemble(&mut self, part: u8, parts: u8, snapshot: Snapshot) -> Option<Snapshot> {
+        let newer = match &self.assembling {
+            Some((current, _)) => snapshot.tick > current.tick,
+            None => true,
+        };
+        if newer {
+            self.assembling = Some((Snapshot { chasers: Vec::new(), ..snapshot.clone() }, vec![false; parts as usize]));
+        }
+
+        let (current, received) = self.assembling.as_mut()?;
+        if current.tick != snapshot.tick || received.len() != parts as usize || received[part as usize] {
+            return None;
+        }
+        received[part as usize] = true;
+        current.chasers.extend(snapshot.chasers);
+
+        if received.iter().all(|r| *r) {
+            self.assembling.take().map(|(snapshot, _)| snapshot)
+        } else {
+            None
+        }
+    }
+
+    fn send(&self, message: ClientMessage) {
+        if let Err(e) = self.socket.send_to(&message.to_bytes(), self.server) {
+            warn!("Could not send to {}: {}", self.server, e);
+        }
+    }
+}
+
+#[derive(Default)]
+struct RemoteEntities {
+    players: HashMap<u8, Entity>,
+    chasers: HashMap<u64, Entity>,
+}
+
+// Another player's Earth. Just a sprite, the server does all the colliding.
+#[derive(Component)]
+struct RemoteEarth;
+
+fn set_center_text(query: &mut Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>)>, value: &str) {
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.to_string();
+    }
+}
+
+// The menu is skipped, the server picks the difficulty and when rounds start
+fn show_connecting(
+    client: Res<NetClient>,
+    mut in_menu: ResMut<InMenu>,
+    mut center_text: Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>)>,
+) {
+    in_menu.0 = false;
+    set_center_text(&mut center_text, &format!("Connecting to {}", client.server));
+}
+
+fn receive_server_messages(
+    time: Res<Time>,
+    mut client: ResMut<NetClient>,
+    mut buffer: Local<Vec<u8>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut player_count: ResMut<PlayerCount>,
+    mut remote: ResMut<RemoteEntities>,
+    mut restart_events: EventWriter<RestartRun>,
+    mut center_text: Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>)>,
+) {
+    let now = time.seconds_since_startup();
+    buffer.resize(MAX_MESSAGE_SIZE, 0);
+
+    loop {
+        let (length, address) = match client.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            // Windows reports the server not being there yet on the next receive
+            Err(e) if e.kind() == ErrorKind::ConnectionReset => continue,
+            Err(e) => {
+                warn!("Could not receive: {}", e);
+                break;
+            }
+        };
+
+        if address != client.server {
+            continue;
+        }
+        let message = match ServerMessage::from_bytes(&buffer[..length]) {
+            Some(message) => message,
+            None => continue,
+        };
+        client.last_heard = now;
+
+        match message {
+            ServerMessage::Welcome { slot, difficulty: server_difficulty } => {
+                if client.slot.is_none() {
+                    info!("Joined {} as player {}", client.server, slot + 1);
+                    set_center_text(&mut center_text, "");
+                }
+                client.slot = Some(slot);
+                *difficulty = server_difficulty;
+                player_count.0 = 1;
+            }
+            ServerMessage::Full => {
+                if client.slot.is_none() {
+                    set_center_text(&mut center_text, "Server is full");
+                }
+            }
+            ServerMessage::SnapshotPart { part, parts, snapshot } => {
+                if client.slot.is_none() || client.snapshots.back().map_or(false, |s| s.tick >= snapshot.tick) {
+                    continue;
+                }
+                let snapshot = match client.assemble(part, parts, snapshot) {
+                    Some(snapshot) => snapshot,
+                    None => continue,
+                };
+
+                // A new round clears out the last one, see `reset_game`
+                if client.round != Some(snapshot.round) {
+                    client.round = Some(snapshot.round);
+                    client.snapshots.clear();
+                    remote.chasers.clear();
+                    restart_events.send(RestartRun);
+                }
+
+                let offset = snapshot.time() - now;
+                client.clock_offset = Some(match client.clock_offset {
+                    Some(current) => current + (offset - current) * 0.1,
+                    None => offset,
+                });
+                client.correction = snapshot.players.iter().find(|p| Some(p.slot) == client.slot).copied();
+                client.snapshots.push_back(snapshot);
+                if client.snapshots.len() > MAX_BUFFERED_SNAPSHOTS {
+                    client.snapshots.pop_front();
+                }
+            }
+        }
+    }
+
+    if client.slot.is_none() {
+        if client.last_hello.map_or(true, |last| now - last >= HELLO_INTERVAL) {
+            client.send(ClientMessage::Hello);
+            client.last_hello = Some(now);
+        }
+    } else if !client.lost && now - client.last_heard > TIMEOUT_SECONDS {
+        client.lost = true;
+        warn!("Lost connection to {}", client.server);
+        set_center_text(&mut center_text, "Lost connection to the server");
+    }
+}
+
+fn send_input(client: Res<NetClient>, tick: Res<GameTick>) {
+    if client.slot.is_some() && !client.lost {
+        client.send(ClientMessage::Input(tick.actions[0]));
+    }
+}
+
+// The two snapshots either side of `time` and how far between them it is
+fn snapshots_around(snapshots: &VecDeque<Snapshot>, time: f64) -> Option<(&Snapshot, &Snapshot, f32)> {
+    let newest = snapshots.back()?;
+    if time >= newest.time() {
+        return Some((newest, newest, 1.));
+    }
+
+    let index = snapshots.iter().rposition(|s| s.time() <= time);
+    match index {
+        Some(index) => {
+            let (from, to) = (&snapshots[index], &snapshots[index + 1]);
+            let t = (time - from.time()) / (to.time() - from.time());
+            Some((from, to, t as f32))
+        }
+        None => Some((&snapshots[0], &snapshots[0], 1.)),
+    }
+}
+
+fn apply_snapshots(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut client: ResMut<NetClient>,
+    mut remote: ResMut<RemoteEntities>,
+    (play_area, chaser_sprite, chicken_sprite): (Res<PlayArea>, Res<ChaserSprite>, Res<ChickenSprite>),
+    (mut chaser_count, mut survival_time): (ResMut<ChaserCount>, ResMut<SurvivalTime>),
+    mut player_query: Query<(&mut Transform, &mut Velocity, &mut PlayerHealth), With<Player>>,
+    mut earth_query: Query<
+        (&mut Transform, &mut Visibility),
+        (With<RemoteEarth>, Without<Player>, Without<ChasingEnemy>),
+    >,
+    mut chaser_query: Query<
+        (&mut Transform, &mut Velocity, &mut Sprite),
+        (With<ChasingEnemy>, Without<Player>, Without<RemoteEarth>),
+    >,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+    asset_server: Res<AssetServer>,
+) {
+    if let Some(state) = client.correction.take() {
+        for (mut transform, mut velocity, mut health) in player_query.iter_mut() {
+            transform.translation = state.position.extend(transform.translation.z);
+            velocity.linear = state.velocity.extend(0.);
+            health.0 = state.health;
+        }
+    }
+
+    let render_time = match client.clock_offset {
+        Some(offset) => time.seconds_since_startup() + offset - INTERPOLATION_DELAY,
+        None => return,
+    };
+    let (from, to, t) = match snapshots_around(&client.snapshots, render_time) {
+        Some(around) => around,
+        None => return,
+    };
+    let step = (to.time() - from.time()).max(f64::EPSILON) as f32;
+
+    let chaser_size = play_area.0.x / 40.;
+    let previous: HashMap<u64, Vec2> = from.chasers.iter().map(|c| (c.id, c.position)).collect();
+    for chaser in to.chasers.iter() {
+        let start = previous.get(&chaser.id).copied().unwrap_or(chaser.position);
+        let position = start.lerp(chaser.position, t);
+        let size = Vec2::splat(chaser_size * chaser.size_scale);
+
+        match remote.chasers.get(&chaser.id).and_then(|e| chaser_query.get_mut(*e).ok()) {
+            Some((mut transform, mut velocity, mut sprite)) => {
+                transform.translation = position.extend(0.0);
+                // Trails and threat indicators read the velocity
+                velocity.linear = ((chaser.position - start) / step).extend(0.);
+                sprite.custom_size = Some(size);
+            }
+            None => {
+                let texture = if chaser.chicken { chicken_sprite.0.clone() } else { chaser_sprite.0.clone() };
+                let mut entity = commands.spawn_bundle(SpriteBundle {
+                    texture,
+                    sprite: Sprite {
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(position.extend(0.0)),
+                    ..Default::default()
+                });
+                entity
+                    .insert(ChasingEnemy)
+                    .insert(SizeScale(chaser.size_scale))
+                    .insert(Velocity::default());
+                if chaser.chicken {
+                    entity.insert(Chicken);
+                }
+                remote.chasers.insert(chaser.id, entity.id());
+            }
+        }
+    }
+
+    remote.chasers.retain(|id, entity| {
+        let alive = to.chasers.iter().any(|c| c.id == *id);
+        if !alive {
+            commands.entity(*entity).despawn();
+        }
+        alive
+    });
+
+    let own_slot = client.slot;
+    let player_size = play_area.0.x / 20.;
+    for player in to.players.iter().filter(|p| Some(p.slot) != own_slot) {
+        let start = from
+            .players
+            .iter()
+            .find(|p| p.slot == player.slot)
+            .map_or(player.position, |p| p.position);
+        let position = start.lerp(player.position, t);
+
+        match remote.players.get(&player.slot).and_then(|e| earth_query.get_mut(*e).ok()) {
+            Some((mut transform, mut visibility)) => {
+                transform.translation = position.extend(0.0);
+                visibility.is_visible = player.health > 0;
+            }
+            None => {
+                let entity = commands
+                    .spawn_bundle(SpriteBundle {
+                        texture: asset_server.load("sprites/PlayerEarth.png"),
+                        sprite: Sprite {
+                            color: PLAYER_COLORS[player.slot as usize % PLAYER_COLORS.len()],
+                            custom_size: Some(Vec2::splat(player_size)),
+                            ..Default::default()
+                        },
+                        transform: Transform::from_translation(position.extend(0.0)),
+                        ..Default::default()
+                    })
+                    .insert(RemoteEarth)
+                    .id();
+                remote.players.insert(player.slot, entity);
+            }
+        }
+    }
+
+    remote.players.retain(|slot, entity| {
+        let connected = to.players.iter().any(|p| p.slot == *slot);
+        if !connected {
+            commands.entity(*entity).despawn();
+        }
+        connected
+    });
+
+    chaser_count.current = to.chasers.len() as u32;
+    survival_time.0 = to.survival_time;
+    for mut text in enemy_count_text_query.iter_mut() {
+        text.sections[1].value = chaser_count.current.to_string();
+    }
+}
+
+// The local Earth is always spawned as the first player, so it takes on the color of
+// whichever slot the server actually gave it, the same one everyone else sees it in
+fn tint_own_earth(client: Res<NetClient>, mut player_query: Query<&mut Sprite, With<Player>>) {
+    let color = match client.slot {
+        Some(slot) => PLAYER_COLORS[slot as usize % PLAYER_COLORS.len()],
+        None => return,
+    };
+    for mut sprite in player_query.iter_mut() {
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+// The server moves on to the next round by itself once everyone is down
+fn show_spectating(player_died: Res<PlayerDied>, mut sub_center_text: Query<&mut Text, With<SubCenterText>>) {
+    if player_died.is_changed() && player_died.0 {
+        for mut text in sub_center_text.iter_mut() {
+            text.sections[0].value = String::from("Watching until the next round starts");
+        }
+    }
+}
+
+// Lets the server free the slot right away instead of waiting for the timeout
+fn say_goodbye(client: Res<NetClient>, exit_events: EventReader<bevy::app::AppExit>) {
+    if client.slot.is_some() && !exit_events.is_empty() {
+        client.send(ClientMessage::Bye);
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bevy::{app::ScheduleRunnerSettings, asset::AssetPlugin, log::LogPlugin, prelude::*, transform::TransformPlugin};
+use heron::{prelude::*, PhysicsSteps};
+use rand::SeedableRng;
+
+use crate::{
+    arena::Arena,
+    calculate_health,
+    chickens::{catch_chickens, move_chickens, reset_chickens, ChickenCaught, ChickenSettings, ChickensCaught},
+    controls::ActionSet,
+    coop::{start_position, MAX_PLAYERS},
+    daily::DailyChallenge,
+    difficulty::Difficulty,
+    enemies::EnemyPlugin,
+    fragments::split_meteors,
+    high_scores::{self, SurvivalTime},
+    increase_spawn_size, move_chasing_enemies,
+    net::{
+        ChaserState, ClientMessage, PlayerState, ServerMessage, Snapshot, SERVER_TICK_RATE, SNAPSHOT_INTERVAL,
+        TIMEOUT_SECONDS,
+    },
+    player_movement,
+    replay::GameTick,
+    reseed_random_generator, reset_game, spawn_chasers, spawn_player, update_downed_players,
+    versus::Versus,
+    ChaserCount, ChaserSprite, ChasingEnemy, Chicken, ChickenSprite, EmptyHeartSprite, FullHeartSprite, GamePaused,
+    GodMode, IncreaseSpawnSizeTimer, NextRunSeed, PlayArea, PlayerDied, PlayerHealth, PlayerHit, PlayerSlot,
+    RandomGenerator, RestartRun, RunSeed, SizeScale, SpawnChasers, SpawnSizeIncrements, SpawnTimer, PHYSICS_TIME_SCALE,
+};
+
+// The headless server runs the same systems as a single player game, minus everything
+// that draws or plays sounds, with one player per connected client. There's no window
+// to size the play area from, so it uses the smallest resolution the game offers.
+
+const PLAY_AREA: Vec2 = Vec2::new(1200., 800.);
+// How long everyone gets to look at the death screen before the next round starts
+const ROUND_RESTART_DELAY: f32 = 3.;
+
+struct ConnectedClient {
+    address: SocketAddr,
+    slot: usize,
+    actions: ActionSet,
+    last_heard: f64,
+}
+
+struct Server {
+    socket: UdpSocket,
+    clients: Vec<ConnectedClient>,
+    tick: u32,
+    round: u32,
+    restart_timer: Timer,
+}
+
+impl Server {
+    fn send(&self, address: SocketAddr, message: &ServerMessage) {
+        if let Err(e) = self.socket.send_to(&message.to_bytes(), address) {
+            warn!("Could not send to {}: {}", address, e);
+        }
+    }
+}
+
+// Only returns if the port can't be listened on, otherwise it runs until the process is stopped
+pub fn run_headless(port: u16) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    socket.set_nonblocking(true)?;
+
+    let seed: u64 = rand::random();
+    // Stays paused until somebody joins, see `server_tick`
+    let mut physics_time = PhysicsTime::new(PHYSICS_TIME_SCALE);
+    physics_time.pause();
+
+    App::new()
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(1. / SERVER_TICK_RATE)))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        .add_plugin(TransformPlugin)
+        // Only for the enemy registry, see `enemies`
+        .add_plugin(AssetPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(PhysicsPlugin::default())
+        .insert_resource(Server {
+            socket,
+            clients: Vec::new(),
+            tick: 0,
+            round: 0,
+            restart_timer: Timer::from_seconds(ROUND_RESTART_DELAY, false),
+        })
+        .insert_resource(physics_time)
+        .insert_resource(PlayArea(PLAY_AREA))
+        .insert_resource(Difficulty::Normal)
+        .insert_resource(GamePaused(true))
+        .insert_resource(PlayerDied(false))
+        .insert_resource(SurvivalTime(0.0))
+        .insert_resource(ChaserCount::new(0, 1000))
+        .insert_resource(SpawnSizeIncrements(0))
+        .insert_resource(SpawnTimer(Timer::from_seconds(0.5, true)))
+        .insert_resource(IncreaseSpawnSizeTimer(Timer::from_seconds(5.0, true)))
+        .insert_resource(RunSeed(seed))
+        .insert_resource(RandomGenerator(rand::rngs::StdRng::seed_from_u64(seed)))
+        // Nothing is drawn, so the sprites only need to be something
+        .insert_resource(ChaserSprite(Handle::default()))
+        .insert_resource(ChickenSprite(Handle::default()))
+        .insert_resource(FullHeartSprite(Handle::default()))
+        .insert_resource(EmptyHeartSprite(Handle::default()))
+        .init_resource::<NextRunSeed>()
+        .init_resource::<GodMode>()
+        .init_resource::<DailyChallenge>()
+        .init_resource::<GameTick>()
+        // Never turned on, network games are always co-op
+        .init_resource::<Versus>()
+        // Never set either, network games are always played in open space
+        .init_resource::<Arena>()
+        .init_resource::<ChickenSettings>()
+        .init_resource::<ChickensCaught>()
+        .add_event::<RestartRun>()
+        .add_event::<PlayerHit>()
+        .add_event::<SpawnChasers>()
+        .add_event::<ChickenCaught>()
+        .add_startup_system(announce_server)
+        .add_system_to_stage(CoreStage::PreUpdate, receive_client_messages.label("receive_client_messages"))
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            reseed_random_generator.label("reseed_random_generator").after("receive_client_messages"),
+        )
+        .add_system_to_stage(CoreStage::PreUpdate, server_tick.after("receive_client_messages"))
+        .add_system(player_movement)
+        .add_system(move_chasing_enemies)
+        .add_system(spawn_chasers)
+        .add_system(split_meteors)
+        .add_system(move_chickens)
+        .add_system(catch_chickens)
+        .add_system(reset_chickens)
+        .add_system(increase_spawn_size)
+        .add_system(calculate_health.label("calculate_health"))
+        .add_system(update_downed_players.after("calculate_health"))
+        .add_system(high_scores::track_survival_time)
+        .add_system(reset_game)
+        .add_system(start_round)
+        .add_system(restart_when_everyone_is_down)
+        .add_system_to_stage(CoreStage::PostUpdate, send_snapshots)
+        .run();
+    Ok(())
+}
+
+fn announce_server(server: Res<Server>) {
+    match server.socket.local_addr() {
+        Ok(address) => info!("Earth Escape server listening on port {}", address.port()),
+        Err(_) => info!("Earth Escape server listening"),
+    }
+}
+
+fn receive_client_messages(
+    mut commands: Commands,
+    mut server: ResMut<Server>,
+    time: Res<Time>,
+    difficulty: Res<Difficulty>,
+    play_area: Res<PlayArea>,
+    mut restart_events: EventWriter<RestartRun>,
+    player_query: Query<(Entity, &PlayerSlot)>,
+) {
+    let now = time.seconds_since_startup();
+    // Client messages are only ever a few bytes
+    let mut buffer = [0u8; 64];
+    let mut left = Vec::new();
+
+    loop {
+        let (length, address) = match server.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            // Windows reports an earlier send to a closed port on the next receive
+            Err(e) if e.kind() == ErrorKind::ConnectionReset => continue,
+            Err(e) => {
+                warn!("Could not receive: {}", e);
+                break;
+            }
+        };
+
+        let message = match ClientMessage::from_bytes(&buffer[..length]) {
+            Some(message) => message,
+            None => continue,
+        };
+        let known = server.clients.iter().position(|c| c.address == address);
+
+        match (message, known) {
+            // The welcome got lost, so send it again
+            (ClientMessage::Hello, Some(index)) => {
+                let slot = server.clients[index].slot as u8;
+                server.send(address, &ServerMessage::Welcome { slot, difficulty: *difficulty });
+            }
+            (ClientMessage::Hello, None) => {
+                let slot = (0..MAX_PLAYERS).find(|slot| server.clients.iter().all(|c| c.slot != *slot));
+                match slot {
+                    Some(slot) => {
+                        // The first player in starts a fresh round
+                        if server.clients.is_empty() {
+                            restart_events.send(RestartRun);
+                        }
+                        server.clients.push(ConnectedClient {
+                            address,
+                            slot,
+                            actions: ActionSet::default(),
+                            last_heard: now,
+                        });
+                        spawn_player(
+                            &mut commands,
+                            Handle::default(),
+                            slot,
+                            MAX_PLAYERS,
+                            play_area.0.x,
+                            difficulty.settings().hearts,
+                        );
+                        server.send(address, &ServerMessage::Welcome { slot: slot as u8, difficulty: *difficulty });
+                        info!("Player {} joined from {}", slot + 1, address);
+                    }
+                    None => server.send(address, &ServerMessage::Full),
+                }
+            }
+            (ClientMessage::Input(actions), Some(index)) => {
+                let client = &mut server.clients[index];
+                client.actions = actions;
+                client.last_heard = now;
+            }
+            (ClientMessage::Bye, Some(index)) => left.push(server.clients.remove(index)),
+            _ => {}
+        }
+    }
+
+    while let Some(index) = server.clients.iter().position(|c| now - c.last_heard > TIMEOUT_SECONDS) {
+        left.push(server.clients.remove(index));
+    }
+
+    for client in left {
+        for (entity, PlayerSlot(slot)) in player_query.iter() {
+            if *slot == client.slot {
+                commands.entity(entity).despawn();
+            }
+        }
+        info!("Player {} left", client.slot + 1);
+    }
+}
+
+// The server's version of `advance_tick`, with the actions coming from the clients
+fn server_tick(
+    mut server: ResMut<Server>,
+    player_died: Res<PlayerDied>,
+    mut game_paused: ResMut<GamePaused>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut tick: ResMut<GameTick>,
+    mut physics_steps: ResMut<PhysicsSteps>,
+) {
+    // Nothing moves while nobody is connected
+    let paused = server.clients.is_empty();
+    if paused != game_paused.0 {
+        if paused {
+            physics_time.pause();
+        } else {
+            physics_time.resume();
+        }
+        game_paused.0 = paused;
+    }
+
+    let running = !paused && !player_died.0;
+    let mut actions = [ActionSet::default(); MAX_PLAYERS];
+    for client in server.clients.iter() {
+        actions[client.slot] = client.actions;
+    }
+
+    *tick = GameTick {
+        running,
+        delta: if running { Duration::from_secs_f64(1. / SERVER_TICK_RATE) } else { Duration::ZERO },
+        actions,
+    };
+    *physics_steps = PhysicsSteps::every_frame(tick.delta);
+    server.tick += 1;
+}
+
+// Players stay connected between rounds, so they are put back rather than respawned
+fn start_round(
+    mut restart_events: EventReader<RestartRun>,
+    mut server: ResMut<Server>,
+    difficulty: Res<Difficulty>,
+    play_area: Res<PlayArea>,
+    mut player_query: Query<(&PlayerSlot, &mut Transform, &mut Velocity, &mut PlayerHealth)>,
+) {
+    if restart_events.iter().count() > 0 {
+        server.round += 1;
+        server.restart_timer.reset();
+
+        for (PlayerSlot(slot), mut transform, mut velocity, mut health) in player_query.iter_mut() {
+            *transform = Transform::from_translation(start_position(*slot, MAX_PLAYERS, play_area.0.x));
+            *velocity = Velocity::from_linear(Vec3::ZERO);
+            health.0 = difficulty.settings().hearts;
+        }
+
+        info!("Round {} started with {} players", server.round, server.clients.len());
+    }
+}
+
+fn restart_when_everyone_is_down(
+    time: Res<Time>,
+    player_died: Res<PlayerDied>,
+    mut server: ResMut<Server>,
+    mut restart_events: EventWriter<RestartRun>,
+) {
+    if player_died.0 && server.restart_timer.tick(time.delta()).just_finished() {
+        restart_events.send(RestartRun);
+    }
+}
+
+fn send_snapshots(
+    server: Res<Server>,
+    survival_time: Res<SurvivalTime>,
+    player_query: Query<(&PlayerSlot, &Transform, &Velocity, &PlayerHealth)>,
+    // Chickens go out as chasers too, with the flag set
+    chaser_query: Query<(Entity, &Transform, &SizeScale, Option<&Chicken>), Or<(With<ChasingEnemy>, With<Chicken>)>>,
+) {
+    if server.clients.is_empty() || server.tick % SNAPSHOT_INTERVAL != 0 {
+        return;
+    }
+
+    let snapshot = Snapshot {
+        tick: server.tick,
+        round: server.round,
+        survival_time: survival_time.0,
+        players: player_query
+            .iter()
+            .map(|(PlayerSlot(slot), transform, velocity, health)| PlayerState {
+                slot: *slot as u8,
+                position: transform.translation.truncate(),
+                velocity: velocity.linear.truncate(),
+                health: health.0,
+            })
+            .collect(),
+        chasers: chaser_query
+            .iter()
+            .map(|(entity, transform, SizeScale(size_scale), chicken)| ChaserState {
+                id: entity.to_bits(),
+                position: transform.translation.truncate(),
+                size_scale: *size_scale,
+                chicken: chicken.is_some(),
+            })
+            .collect(),
+    };
+
+    for part in snapshot.parts() {
+        let bytes = part.to_bytes();
+        for client in server.clients.iter() {
+            if let Err(e) = server.socket.send_to(&bytes, client.address) {
+                warn!("Could not send a snapshot to {}: {}", client.address, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::Rng;
+
+use crate::{
+    camera::CameraView, chickens::ChickenCaught, is_enemy, is_player, shapes::GeneratedShapes, ChasingEnemy, Downed,
+    GamePaused, Player, PlayerDied, PlayerHealth, SizeScale,
+};
+
+// Particles are simulated on the CPU in a plain Vec and drawn with a fixed pool of
+// sprites, one per allowed particle, so nothing is spawned or despawned while playing.
+// They use `thread_rng` rather than `RandomGenerator` so effects never change the run.
+
+const BURST_Z: f32 = 0.5;
+const TRAIL_Z: f32 = -0.02;
+
+pub struct ParticleSettings {
+    // Copied from `Settings`, where the player can change it. The defaults below are
+    // only what a new settings file starts with.
+    pub max_particles: usize,
+    pub trails: bool,
+    // Trail particles per second for a chaser with a `SizeScale` of 1
+    pub trail_rate: f32,
+}
+
+impl Default for ParticleSettings {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default() -> Self {
+        ParticleSettings {
+            max_particles: 1500,
+            trails: true,
+            trail_rate: 12.,
+        }
+    }
+
+    // Browsers on low end devices struggle with lots of sprites, so be far more conservative
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Self {
+        ParticleSettings {
+            max_particles: 400,
+            trails: true,
+            trail_rate: 6.,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ParticleEffect {
+    Impact,
+    Death,
+    Pickup,
+    Pulse,
+}
+
+// Send this to spawn a one-off burst of particles
+pub struct ParticleBurst {
+    pub effect: ParticleEffect,
+    pub position: Vec2,
+    pub scale: f32,
+}
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ParticleSettings>()
+            .init_resource::<Particles>()
+            .add_event::<ParticleBurst>()
+            .add_system(emit_impact_particles)
+            .add_system(emit_death_particles)
+            .add_system(emit_pickup_particles)
+            .add_system(emit_trail_particles)
+            .add_system(spawn_bursts.label("spawn_bursts"))
+            .add_system(simulate_particles.label("simulate_particles").after("spawn_bursts"))
+            .add_system(sync_particle_pool.label("sync_particle_pool"))
+            .add_system(draw_particles.after("simulate_particles").after("sync_particle_pool"));
+    }
+}
+
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    start_size: f32,
+    end_size: f32,
+    start_color: Color,
+    end_color: Color,
+    z: f32,
+}
+
+#[derive(Default)]
+struct Particles(Vec<Particle>);
+
+impl Particles {
+    fn push(&mut self, settings: &ParticleSettings, particle: Particle) {
+        if self.0.len() < settings.max_particles {
+            self.0.push(particle);
+        }
+    }
+}
+
+#[derive(Component)]
+struct ParticleSprite(usize);
+
+fn emit_impact_particles(
+    mut events: EventReader<CollisionEvent>,
+    mut bursts: EventWriter<ParticleBurst>,
+    player_died: Res<PlayerDied>,
+    player_query: Query<&Transform, With<Player>>,
+    chaser_query: Query<(&Transform, &SizeScale), With<ChasingEnemy>>,
+) {
+    for event in events.iter() {
+        if player_died.0 || !event.is_started() {
+            continue;
+        }
+
+        let (layers_1, layers_2) = event.collision_layers();
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (player, chaser) = if is_player(layers_1) && is_enemy(layers_2) {
+            (entity_1, entity_2)
+        } else if is_player(layers_2) && is_enemy(layers_1) {
+            (entity_2, entity_1)
+        } else {
+            continue;
+        };
+
+        if let (Ok(player_transform), Ok((chaser_transform, SizeScale(size_scale)))) =
+            (player_query.get(player), chaser_query.get(chaser))
+        {
+            bursts.send(ParticleBurst {
+                effect: ParticleEffect::Impact,
+                position: player_transform.translation.lerp(chaser_transform.translation, 0.5).truncate(),
+                scale: *size_scale,
+            });
+        }
+    }
+}
+
+// A co-op player going down bursts right away, the last one when the run ends. The
+// winner of a versus round is still standing, so only players out of hearts burst.
+fn emit_death_particles(
+    player_died: Res<PlayerDied>,
+    player_query: Query<(&Transform, &PlayerHealth), (With<Player>, Without<Downed>)>,
+    downed_query: Query<&Transform, Added<Downed>>,
+    mut bursts: EventWriter<ParticleBurst>,
+) {
+    let mut burst_at = |transform: &Transform| {
+        bursts.send(ParticleBurst {
+            effect: ParticleEffect::Death,
+            position: transform.translation.truncate(),
+            scale: 1.,
+        });
+    };
+
+    downed_query.iter().for_each(&mut burst_at);
+    if player_died.is_changed() && player_died.0 {
+        player_query
+            .iter()
+            .filter(|(_, health)| health.0 == 0)
+            .for_each(|(transform, _)| burst_at(transform));
+    }
+}
+
+fn emit_pickup_particles(mut caught_events: EventReader<ChickenCaught>, mut bursts: EventWriter<ParticleBurst>) {
+    for caught in caught_events.iter() {
+        bursts.send(ParticleBurst {
+            effect: ParticleEffect::Pickup,
+            position: caught.position,
+            scale: 1.,
+        });
+    }
+}
+
+fn emit_trail_particles(
+    settings: Res<ParticleSettings>,
+    mut particles: ResMut<Particles>,
+    game_paused: Res<GamePaused>,
+    time: Res<Time>,
+    windows: Res<Windows>,
+    view: Res<CameraView>,
+    chaser_query: Query<(&Transform, &Velocity, &SizeScale), With<ChasingEnemy>>,
+) {
+    // Trails are the least important effect, so they leave room in the pool for bursts
+    if game_paused.0 || !settings.trails || particles.0.len() * 4 >= settings.max_particles * 3 {
+        return;
+    }
+
+    let camera = view.center;
+    let half_extents = view.half_extents + Vec2::splat(100.);
+    let base_size = windows.get_primary().unwrap().width() / 40.;
+    let mut rng = rand::thread_rng();
+
+    for (transform, velocity, SizeScale(size_scale)) in chaser_query.iter() {
+        let position = transform.translation.truncate();
+        let offset = position - camera;
+        if offset.x.abs() > half_extents.x || offset.y.abs() > half_extents.y {
+            continue;
+        }
+
+        let linear = velocity.linear.truncate();
+        let speed = linear.length();
+        if speed < 50. {
+            continue;
+        }
+
+        let chance = (settings.trail_rate * size_scale * time.delta_seconds()).min(1.);
+        if !rng.gen_bool(chance as f64) {
+            continue;
+        }
+
+        // Start at the back edge of the meteor and drift away from its direction of travel
+        let backwards = -linear / speed;
+        let radius = base_size * size_scale / 2.;
+        particles.push(&settings, Particle {
+            position: position + backwards * radius * 0.8,
+            velocity: backwards * rng.gen_range(20.0..60.0) + random_direction(&mut rng) * 15.,
+            age: 0.,
+            lifetime: rng.gen_range(0.25..0.5) * size_scale.sqrt(),
+            start_size: radius * 0.6,
+            end_size: radius * 0.1,
+            start_color: Color::rgba(1.0, 0.7, 0.2, 0.8),
+            end_color: Color::rgba(0.6, 0.1, 0.0, 0.0),
+            z: TRAIL_Z,
+        });
+    }
+}
+
+fn spawn_bursts(
+    mut bursts: EventReader<ParticleBurst>,
+    settings: Res<ParticleSettings>,
+    mut particles: ResMut<Particles>,
+    windows: Res<Windows>,
+) {
+    let base_size = windows.get_primary().unwrap().width() / 160.;
+    let mut rng = rand::thread_rng();
+
+    for burst in bursts.iter() {
+        let (count, speed, lifetime, start_color, end_color) = match burst.effect {
+            ParticleEffect::Impact => (
+                (14. * burst.scale) as usize,
+                80.0..220.0,
+                0.3..0.6,
+                Color::rgba(1.0, 0.85, 0.3, 1.0),
+                Color::rgba(0.9, 0.2, 0.0, 0.0),
+            ),
+            ParticleEffect::Death => (
+                120,
+                100.0..450.0,
+                0.8..1.6,
+                Color::rgba(0.3, 0.6, 1.0, 1.0),
+                Color::rgba(0.1, 0.8, 0.3, 0.0),
+            ),
+            ParticleEffect::Pickup => (
+                24,
+                120.0..160.0,
+                0.4..0.6,
+                Color::rgba(1.0, 0.9, 0.3, 1.0),
+                Color::rgba(1.0, 1.0, 1.0, 0.0),
+            ),
+            // Fast enough to reach about as far as the pulse pushes, see `versus`
+            ParticleEffect::Pulse => (
+                60,
+                850.0..1000.0,
+                0.3..0.35,
+                Color::rgba(0.5, 0.9, 1.0, 0.9),
+                Color::rgba(0.2, 0.4, 1.0, 0.0),
+            ),
+        };
+
+        for _ in 0..count {
+            let size = base_size * burst.scale * rng.gen_range(0.6..1.4);
+            particles.push(&settings, Particle {
+                position: burst.position,
+                velocity: random_direction(&mut rng) * rng.gen_range(speed.clone()),
+                age: 0.,
+                lifetime: rng.gen_range(lifetime.clone()),
+                start_size: size,
+                end_size: size * 0.2,
+                start_color,
+                end_color,
+                z: BURST_Z,
+            });
+        }
+    }
+}
+
+fn simulate_particles(
+    mut particles: ResMut<Particles>,
+    game_paused: Res<GamePaused>,
+    time: Res<Time>,
+) {
+    if game_paused.0 {
+        return;
+    }
+
+    let delta = time.delta_seconds();
+    let drag = (1. - 2. * delta).max(0.);
+
+    particles.0.retain(|p| p.age < p.lifetime);
+    for particle in particles.0.iter_mut() {
+        particle.age += delta;
+        particle.position += particle.velocity * delta;
+        particle.velocity *= drag;
+    }
+}
+
+// Keeps one sprite per allowed particle, so lowering `max_particles` also frees the sprites
+fn sync_particle_pool(
+    mut commands: Commands,
+    settings: Res<ParticleSettings>,
+    mut particles: ResMut<Particles>,
+    shapes: Res<GeneratedShapes>,
+    sprite_query: Query<(Entity, &ParticleSprite)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    particles.0.truncate(settings.max_particles);
+
+    let existing = sprite_query.iter().count();
+    if existing < settings.max_particles {
+        for id in existing..settings.max_particles {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: shapes.circle.clone(),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(ParticleSprite(id));
+        }
+    } else {
+        sprite_query
+            .iter()
+            .filter(|(_, ParticleSprite(id))| *id >= settings.max_particles)
+            .for_each(|(e, _)| commands.entity(e).despawn());
+    }
+}
+
+fn draw_particles(
+    particles: Res<Particles>,
+    mut sprite_query: Query<(&ParticleSprite, &mut Transform, &mut Sprite, &mut Visibility)>,
+) {
+    for (ParticleSprite(id), mut transform, mut sprite, mut visibility) in sprite_query.iter_mut() {
+        match particles.0.get(*id) {
+            Some(particle) => {
+                let t = (particle.age / particle.lifetime).min(1.);
+                let size = particle.start_size + (particle.end_size - particle.start_size) * t;
+
+                transform.translation = particle.position.extend(particle.z);
+                sprite.custom_size = Some(Vec2::new(size, size));
+                sprite.color = lerp_color(particle.start_color, particle.end_color, t);
+                visibility.is_visible = true;
+            }
+            None => {
+                if visibility.is_visible {
+                    visibility.is_visible = false;
+                }
+            }
+        }
+    }
+}
+
+fn random_direction(rng: &mut impl Rng) -> Vec2 {
+    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+    Vec2::new(angle.cos(), angle.sin())
+}
+
+fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    Color::rgba(
+        from.r() + (to.r() - from.r()) * t,
+        from.g() + (to.g() - from.g()) * t,
+        from.b() + (to.b() - from.b()) * t,
+        from.a() + (to.a() - from.a()) * t,
+    )
+}
@@ -0,0 +1,219 @@
+use bevy::{prelude::*, ui::Val::Px};
+
+use crate::{
+    controls::{InputAction, KeyBindings},
+    menu::InMenu,
+    settings::Settings,
+    BoldFont, GamePaused, PlayerDied, RestartRun, SetPaused,
+};
+
+const BUTTON_COLOR: Color = Color::rgba(0.15, 0.15, 0.2, 0.9);
+const BUTTON_HOVER_COLOR: Color = Color::rgba(0.25, 0.25, 0.35, 0.9);
+const BUTTON_PRESSED_COLOR: Color = Color::rgba(0.1, 0.45, 0.2, 0.9);
+
+// Sent by the Settings button. Whoever owns the settings screen opens it.
+pub struct OpenSettings;
+
+pub struct PauseMenuPlugin;
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<OpenSettings>()
+            .add_system(sync_pause_menu)
+            .add_system(update_pan_hint)
+            .add_system(pause_menu_buttons);
+    }
+}
+
+#[derive(Component)]
+struct PauseMenuRoot;
+
+#[derive(Component)]
+struct PanHintText;
+
+#[derive(Component, Clone, Copy)]
+enum PauseButton {
+    Resume,
+    Restart,
+    Settings,
+    QuitToMenu,
+}
+
+impl PauseButton {
+    fn label(&self) -> &'static str {
+        match self {
+            PauseButton::Resume => "Resume",
+            PauseButton::Restart => "Restart",
+            PauseButton::Settings => "Settings",
+            PauseButton::QuitToMenu => "Quit to Menu",
+        }
+    }
+}
+
+// The overlay is shown whenever a run is paused, however it got paused
+fn sync_pause_menu(
+    mut commands: Commands,
+    game_paused: Res<GamePaused>,
+    in_menu: Res<InMenu>,
+    player_died: Res<PlayerDied>,
+    bold_font: Res<BoldFont>,
+    settings: Res<Settings>,
+    overlay_query: Query<Entity, With<PauseMenuRoot>>,
+) {
+    let show = game_paused.0 && !in_menu.0 && !player_died.0;
+    let spawned = overlay_query.iter().next().is_some();
+
+    if show && !spawned {
+        spawn_pause_menu(&mut commands, bold_font.0.clone(), &settings.key_bindings);
+    } else if !show && spawned {
+        overlay_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    }
+}
+
+// The movement keys pan the camera while paused, see `camera::update_camera`
+fn pan_hint(bindings: &KeyBindings) -> String {
+    let directions = [InputAction::MoveUp, InputAction::MoveLeft, InputAction::MoveDown, InputAction::MoveRight];
+    let primary: Vec<String> = directions.iter().map(|a| format!("{:?}", bindings.get(*a).primary)).collect();
+    let secondary: Option<Vec<String>> = directions
+        .iter()
+        .map(|a| bindings.get(*a).secondary.map(|key| format!("{:?}", key)))
+        .collect();
+
+    match secondary {
+        Some(secondary) => format!("Move the camera with {} or {}", primary.join("/"), secondary.join("/")),
+        None => format!("Move the camera with {}", primary.join("/")),
+    }
+}
+
+// Keys can be rebound from the settings screen while the overlay is up
+fn update_pan_hint(settings: Res<Settings>, mut hint_query: Query<&mut Text, With<PanHintText>>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for mut text in hint_query.iter_mut() {
+        text.sections[0].value = pan_hint(&settings.key_bindings);
+    }
+}
+
+fn spawn_pause_menu(commands: &mut Commands, font: Handle<Font>, bindings: &KeyBindings) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(PauseMenuRoot)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect {
+                        bottom: Px(24.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Paused",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 82.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            for button in [
+                PauseButton::Resume,
+                PauseButton::Restart,
+                PauseButton::Settings,
+                PauseButton::QuitToMenu,
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Px(320.0), Px(64.0)),
+                            margin: Rect::all(Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: BUTTON_COLOR.into(),
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|button_parent| {
+                        button_parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                button.label(),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 36.0,
+                                    color: Color::WHITE,
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect {
+                        top: Px(16.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    pan_hint(bindings),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 24.0,
+                        color: Color::GRAY,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(PanHintText);
+        });
+}
+
+fn pause_menu_buttons(
+    mut button_query: Query<(&Interaction, &PauseButton, &mut UiColor), Changed<Interaction>>,
+    mut pause_events: EventWriter<SetPaused>,
+    mut restart_events: EventWriter<RestartRun>,
+    mut settings_events: EventWriter<OpenSettings>,
+    mut in_menu: ResMut<InMenu>,
+) {
+    for (interaction, button, mut color) in button_query.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *color = BUTTON_PRESSED_COLOR.into();
+                match button {
+                    PauseButton::Resume => pause_events.send(SetPaused(false)),
+                    PauseButton::Restart => restart_events.send(RestartRun),
+                    PauseButton::Settings => settings_events.send(OpenSettings),
+                    // Clears the run away too, so the menu doesn't sit in front of it
+                    PauseButton::QuitToMenu => {
+                        in_menu.0 = true;
+                        restart_events.send(RestartRun);
+                    }
+                }
+            }
+            Interaction::Hovered => *color = BUTTON_HOVER_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::{camera::CameraView, shapes::GeneratedShapes, Layer};
+
+// Draws what heron sees on top of the sprites: an outline for every collider on screen
+// and a line for its velocity. Both are plain sprites from a pool that only grows,
+// so turning it on mid-run with a thousand chasers doesn't allocate every frame.
+
+const DEBUG_Z: f32 = 20.0;
+const TOGGLE_KEY: KeyCode = KeyCode::F4;
+// The velocity line shows where the body will be this many seconds from now
+const VELOCITY_SECONDS: f32 = 0.25;
+const LINE_WIDTH: f32 = 2.0;
+
+#[derive(Default)]
+pub struct PhysicsDebugSettings {
+    pub enabled: bool,
+}
+
+pub struct PhysicsDebugPlugin;
+
+impl Plugin for PhysicsDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<PhysicsDebugSettings>()
+            .init_resource::<PhysicsDebugPoolSize>()
+            .add_system(toggle_physics_debug)
+            .add_system(sync_physics_debug_pool.label("sync_physics_debug_pool"))
+            .add_system(
+                draw_physics_debug
+                    .after("sync_physics_debug_pool")
+                    .after("camera"),
+            );
+    }
+}
+
+// One outline and one velocity line per id
+#[derive(Component)]
+struct DebugOutline(usize);
+
+#[derive(Component)]
+struct DebugVelocity(usize);
+
+// How many bodies were on screen last frame, which is how big the pool needs to be
+#[derive(Default)]
+struct PhysicsDebugPoolSize(usize);
+
+fn toggle_physics_debug(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<PhysicsDebugSettings>) {
+    if keyboard_input.just_pressed(TOGGLE_KEY) {
+        settings.enabled = !settings.enabled;
+    }
+}
+
+fn sync_physics_debug_pool(
+    mut commands: Commands,
+    needed: Res<PhysicsDebugPoolSize>,
+    outline_query: Query<&DebugOutline>,
+) {
+    let existing = outline_query.iter().count();
+
+    for id in existing..needed.0 {
+        commands
+            .spawn_bundle(SpriteBundle {
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(DebugOutline(id));
+        commands
+            .spawn_bundle(SpriteBundle {
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(DebugVelocity(id));
+    }
+}
+
+fn layer_color(layers: Option<&CollisionLayers>) -> Color {
+    match layers {
+        Some(layers) if layers.contains_group(Layer::Player) => Color::CYAN,
+        Some(layers) if layers.contains_group(Layer::Enemies) => Color::RED,
+        Some(layers) if layers.contains_group(Layer::World) => Color::LIME_GREEN,
+        _ => Color::WHITE,
+    }
+}
+
+fn draw_physics_debug(
+    settings: Res<PhysicsDebugSettings>,
+    view: Res<CameraView>,
+    shapes: Res<GeneratedShapes>,
+    mut pool_size: ResMut<PhysicsDebugPoolSize>,
+    body_query: Query<(&Transform, &CollisionShape, Option<&Velocity>, Option<&CollisionLayers>), With<RigidBody>>,
+    mut outline_query: Query<
+        (&DebugOutline, &mut Transform, &mut Sprite, &mut Handle<Image>, &mut Visibility),
+        (Without<RigidBody>, Without<DebugVelocity>),
+    >,
+    mut velocity_query: Query<
+        (&DebugVelocity, &mut Transform, &mut Sprite, &mut Visibility),
+        (Without<RigidBody>, Without<DebugOutline>),
+    >,
+) {
+    if !settings.enabled {
+        if settings.is_changed() {
+            outline_query.iter_mut().for_each(|(_, _, _, _, mut visibility)| visibility.is_visible = false);
+            velocity_query.iter_mut().for_each(|(_, _, _, mut visibility)| visibility.is_visible = false);
+        }
+        return;
+    }
+
+    // (position, rotation, outline image, outline size, velocity, color) for everything on screen
+    let bodies: Vec<(Vec2, Quat, Handle<Image>, Vec2, Vec2, Color)> = body_query
+        .iter()
+        .filter_map(|(transform, shape, velocity, layers)| {
+            let (image, size) = match shape {
+                CollisionShape::Sphere { radius } => (shapes.ring.clone(), Vec2::splat(radius * 2.)),
+                CollisionShape::Capsule { half_segment, radius } => {
+                    (shapes.ring.clone(), Vec2::splat((half_segment + radius) * 2.))
+                }
+                CollisionShape::Cuboid { half_extends, .. } => {
+                    (shapes.square_outline.clone(), half_extends.truncate() * 2.)
+                }
+                _ => return None,
+            };
+
+            let position = transform.translation.truncate();
+            let offset = position - view.center;
+            let reach = size.max_element() / 2.;
+            if offset.x.abs() > view.half_extents.x + reach || offset.y.abs() > view.half_extents.y + reach {
+                return None;
+            }
+
+            let velocity = velocity.map(|v| v.linear.truncate()).unwrap_or(Vec2::ZERO);
+            Some((position, transform.rotation, image, size, velocity, layer_color(layers)))
+        })
+        .collect();
+
+    pool_size.0 = pool_size.0.max(bodies.len());
+
+    for (DebugOutline(id), mut transform, mut sprite, mut image, mut visibility) in outline_query.iter_mut() {
+        if let Some((position, rotation, body_image, size, _, color)) = bodies.get(*id) {
+            *transform = Transform {
+                translation: position.extend(DEBUG_Z),
+                rotation: *rotation,
+                ..Default::default()
+            };
+            sprite.custom_size = Some(*size);
+            sprite.color = *color;
+            if *image != *body_image {
+                *image = body_image.clone();
+            }
+            visibility.is_visible = true;
+        } else {
+            visibility.is_visible = false;
+        }
+    }
+
+    for (DebugVelocity(id), mut transform, mut sprite, mut visibility) in velocity_query.iter_mut() {
+        match bodies.get(*id) {
+            Some((position, _, _, _, velocity, color)) if *velocity != Vec2::ZERO => {
+                // A 1x1 white sprite stretched from the body's center along its velocity
+                let line = *velocity * VELOCITY_SECONDS;
+                *transform = Transform {
+                    translation: (*position + line / 2.).extend(DEBUG_Z),
+                    rotation: Quat::from_rotation_z(line.y.atan2(line.x)),
+                    ..Default::default()
+                };
+                sprite.custom_size = Some(Vec2::new(line.length(), LINE_WIDTH * view.zoom));
+                sprite.color = *color;
+                visibility.is_visible = true;
+            }
+            _ => visibility.is_visible = false,
+        }
+    }
+}
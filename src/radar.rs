@@ -0,0 +1,197 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    ui::Val::Px,
+};
+
+use crate::{
+    controls::InputAction, coop::player_bounds, settings::Settings, Chicken, ChasingEnemy, Downed, Player, SizeScale,
+};
+
+// The radar is a single image redrawn on the CPU, instead of a UI node per chaser,
+// so it costs the same to show ten chasers as it does to show the full 1000
+const RADAR_PIXELS: u32 = 128;
+const BACKGROUND: [u8; 4] = [10, 20, 40, 170];
+const RING: [u8; 4] = [60, 160, 90, 220];
+const PLAYER_DOT: [u8; 4] = [80, 170, 255, 255];
+const CHASER_DOT: [u8; 4] = [230, 90, 60, 255];
+const CHICKEN_DOT: [u8; 4] = [255, 230, 40, 255];
+
+pub struct RadarSettings {
+    pub visible: bool,
+    // World distance from the player, or the middle of both players in co-op, to the edge of the radar
+    pub range: f32,
+    // On-screen size of the radar widget
+    pub size: f32,
+}
+
+impl Default for RadarSettings {
+    fn default() -> Self {
+        RadarSettings {
+            visible: true,
+            range: 2500.,
+            size: 200.,
+        }
+    }
+}
+
+pub struct RadarPlugin;
+
+impl Plugin for RadarPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<RadarSettings>()
+            .insert_resource(RadarRefreshTimer(Timer::from_seconds(1. / 30., true)))
+            .add_system(toggle_radar)
+            .add_system(draw_radar);
+    }
+}
+
+#[derive(Component)]
+struct Radar;
+
+struct RadarImage(Handle<Image>);
+
+struct RadarRefreshTimer(Timer);
+
+// Called from `setup` to put the radar in the bottom left corner of the HUD
+pub fn spawn_radar(commands: &mut Commands, images: &mut Assets<Image>, settings: &RadarSettings) {
+    let image = images.add(Image::new(
+        Extent3d {
+            width: RADAR_PIXELS,
+            height: RADAR_PIXELS,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        vec![0; (RADAR_PIXELS * RADAR_PIXELS * 4) as usize],
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+
+    commands
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Px(16.0),
+                    bottom: Px(16.0),
+                    ..Default::default()
+                },
+                size: Size::new(Px(settings.size), Px(settings.size)),
+                ..Default::default()
+            },
+            image: image.clone().into(),
+            visibility: Visibility { is_visible: settings.visible },
+            ..Default::default()
+        })
+        .insert(Radar);
+
+    commands.insert_resource(RadarImage(image));
+}
+
+fn toggle_radar(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_settings: Res<Settings>,
+    mut settings: ResMut<RadarSettings>,
+    mut radar_query: Query<(&mut Visibility, &mut Style), With<Radar>>,
+) {
+    if game_settings.key_bindings.just_pressed(InputAction::ToggleRadar, &keyboard_input) {
+        settings.visible = !settings.visible;
+    }
+
+    if settings.is_changed() {
+        for (mut visibility, mut style) in radar_query.iter_mut() {
+            visibility.is_visible = settings.visible;
+            style.size = Size::new(Px(settings.size), Px(settings.size));
+        }
+    }
+}
+
+fn draw_radar(
+    settings: Res<RadarSettings>,
+    radar_image: Res<RadarImage>,
+    mut images: ResMut<Assets<Image>>,
+    mut timer: ResMut<RadarRefreshTimer>,
+    time: Res<Time>,
+    player_query: Query<&Transform, (With<Player>, Without<Downed>)>,
+    chaser_query: Query<(&Transform, &SizeScale, Option<&Chicken>), Or<(With<ChasingEnemy>, With<Chicken>)>>,
+) {
+    if !settings.visible || !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let image = match images.get_mut(&radar_image.0) {
+        Some(image) => image,
+        None => return,
+    };
+
+    let center = RADAR_PIXELS as f32 / 2.;
+    let radius = center - 1.;
+
+    for row in 0..RADAR_PIXELS {
+        for column in 0..RADAR_PIXELS {
+            let dx = column as f32 + 0.5 - center;
+            let dy = row as f32 + 0.5 - center;
+            let distance = (dx * dx + dy * dy).sqrt();
+            let color = if distance > radius {
+                [0, 0, 0, 0]
+            } else if distance > radius - 1.5 || (distance - radius / 2.).abs() < 0.5 {
+                RING
+            } else {
+                BACKGROUND
+            };
+            set_pixel(&mut image.data, column as i32, row as i32, color);
+        }
+    }
+
+    let players: Vec<Vec2> = player_query.iter().map(|t| t.translation.truncate()).collect();
+    let player = match player_bounds(players.iter().copied()) {
+        Some((center, _)) => center,
+        None => return,
+    };
+    let to_radar = radius / settings.range;
+
+    // Chickens are drawn after the meteors so they are never hidden underneath one
+    let mut chickens = Vec::new();
+    for (transform, SizeScale(size_scale), chicken) in chaser_query.iter() {
+        let offset = (transform.translation.truncate() - player) * to_radar;
+        if offset.length() >= radius - 2. {
+            continue;
+        }
+
+        if chicken.is_some() {
+            chickens.push(offset);
+        } else {
+            let dot_radius = if *size_scale > 1.5 { 1 } else { 0 };
+            draw_dot(&mut image.data, center, offset, dot_radius, CHASER_DOT);
+        }
+    }
+
+    for offset in chickens {
+        draw_dot(&mut image.data, center, offset, 1, CHICKEN_DOT);
+    }
+
+    for position in players {
+        draw_dot(&mut image.data, center, (position - player) * to_radar, 2, PLAYER_DOT);
+    }
+}
+
+// Image rows go downwards, so world Y is flipped
+fn draw_dot(data: &mut [u8], center: f32, offset: Vec2, dot_radius: i32, color: [u8; 4]) {
+    let x = (center + offset.x) as i32;
+    let y = (center - offset.y) as i32;
+
+    for dy in -dot_radius..=dot_radius {
+        for dx in -dot_radius..=dot_radius {
+            set_pixel(data, x + dx, y + dy, color);
+        }
+    }
+}
+
+fn set_pixel(data: &mut [u8], x: i32, y: i32, color: [u8; 4]) {
+    if x < 0 || y < 0 || x >= RADAR_PIXELS as i32 || y >= RADAR_PIXELS as i32 {
+        return;
+    }
+
+    let index = ((y as u32 * RADAR_PIXELS + x as u32) * 4) as usize;
+    data[index..index + 4].copy_from_slice(&color);
+}
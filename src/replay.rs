@@ -0,0 +1,576 @@
+use std::time::Duration;
+
+use bevy::{input::InputSystem, prelude::*};
+use heron::PhysicsSteps;
+
+use crate::{
+    arena::{Arena, ArenaShape},
+    boss::Bosses,
+    chickens::ChickenSettings,
+    command_line_value,
+    console::CheatsUsed,
+    controls::ActionSet,
+    coop::{player_actions, ConnectedGamepads, PlayerCount, MAX_PLAYERS},
+    date::unix_time,
+    difficulty::Difficulty,
+    enemies::{Enemies, EnemyRegistry},
+    gravity::Gravity,
+    hazards::Hazards,
+    menu::InMenu,
+    net::offline,
+    settings::Settings,
+    versus::Versus,
+    GamePaused, NextRunSeed, PlayerDied, RestartRun, RunSeed,
+};
+
+// A run only depends on its seed, its difficulty and what the player pressed on each
+// frame, as long as every frame is simulated with the same delta time. So instead of
+// reading `Time` and the keyboard directly, everything that moves the run forward reads
+// `GameTick`, which is filled in once per frame from either the keyboard or a replay.
+// Physics gets the same delta through `PhysicsSteps`.
+
+// A long hitch is simulated as this much time, which also stops meteors tunneling through the player
+const MAX_TICK: Duration = Duration::from_millis(250);
+const MAGIC: &[u8; 4] = b"EERP";
+// Bumped whenever the layout changes, replays from any other version are ignored
+const VERSION: u8 = 1;
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_SAVED_REPLAYS: usize = 20;
+
+// What happens on the current frame of the run. `delta` is zero and `actions` empty
+// while the run is paused, over or hasn't started. `actions` is indexed by player slot.
+#[derive(Default)]
+pub struct GameTick {
+    pub running: bool,
+    pub delta: Duration,
+    pub actions: [ActionSet; MAX_PLAYERS],
+}
+
+#[derive(Clone, Copy)]
+pub struct ReplayFrame {
+    pub actions: [ActionSet; MAX_PLAYERS],
+    pub delta: Duration,
+}
+
+#[derive(Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub players: usize,
+    pub versus: bool,
+    pub gravity: bool,
+    pub arena: Option<(ArenaShape, f32)>,
+    pub bosses: bool,
+    pub chicken_chance: f64,
+    pub hazards: bool,
+    // `EnemyRegistry::hash` of the enemy types the run was played with
+    pub enemies_hash: u64,
+    // Sizes and spawn distances depend on the window, so a replay only matches at the same size
+    pub window_size: (f32, f32),
+    // Unix time in seconds
+    pub recorded_at: u64,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.delta).sum()
+    }
+
+    // Header, then for every frame the actions of each player and the delta in nanoseconds
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(62 + self.frames.len() * (4 + 2 * self.players));
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(Difficulty::ALL.iter().position(|d| *d == self.difficulty).unwrap() as u8);
+        bytes.push(self.players as u8);
+        bytes.push(self.versus as u8);
+        bytes.push(self.gravity as u8);
+        let (shape, size) = match self.arena {
+            None => (0, 0.),
+            Some((ArenaShape::Rectangle, size)) => (1, size),
+            Some((ArenaShape::Circle, size)) => (2, size),
+        };
+        bytes.push(shape);
+        bytes.extend_from_slice(&f32::to_le_bytes(size));
+        bytes.push(self.bosses as u8);
+        bytes.extend_from_slice(&self.chicken_chance.to_le_bytes());
+        bytes.push(self.hazards as u8);
+        bytes.extend_from_slice(&self.enemies_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.window_size.0.to_le_bytes());
+        bytes.extend_from_slice(&self.window_size.1.to_le_bytes());
+        bytes.extend_from_slice(&self.recorded_at.to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in self.frames.iter() {
+            for actions in frame.actions[..self.players].iter() {
+                bytes.extend_from_slice(&actions.0.to_le_bytes());
+            }
+            bytes.extend_from_slice(&(frame.delta.as_nanos() as u32).to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Replay> {
+        let mut reader = ByteReader(bytes);
+        if reader.take(4)? != MAGIC {
+            return None;
+        }
+        if reader.take(1)?[0] != VERSION {
+            return None;
+        }
+
+        let seed = u64::from_le_bytes(reader.array()?);
+        let difficulty = *Difficulty::ALL.get(reader.take(1)?[0] as usize)?;
+        let players = reader.take(1)?[0] as usize;
+        if players == 0 || players > MAX_PLAYERS {
+            return None;
+        }
+        let versus = reader.take(1)?[0] != 0;
+        let gravity = reader.take(1)?[0] != 0;
+        let shape = reader.take(1)?[0];
+        let size = f32::from_le_bytes(reader.array()?);
+        let arena = match shape {
+            0 => None,
+            1 => Some((ArenaShape::Rectangle, size)),
+            2 => Some((ArenaShape::Circle, size)),
+            _ => return None,
+        };
+        let bosses = reader.take(1)?[0] != 0;
+        let chicken_chance = f64::from_le_bytes(reader.array()?);
+        let hazards = reader.take(1)?[0] != 0;
+        let enemies_hash = u64::from_le_bytes(reader.array()?);
+        let window_size = (f32::from_le_bytes(reader.array()?), f32::from_le_bytes(reader.array()?));
+        let recorded_at = u64::from_le_bytes(reader.array()?);
+        let frame_count = u32::from_le_bytes(reader.array()?) as usize;
+
+        let mut frames = Vec::with_capacity(frame_count.min(bytes.len() / (4 + 2 * players)));
+        for _ in 0..frame_count {
+            let mut actions = [ActionSet::default(); MAX_PLAYERS];
+            for slot_actions in actions.iter_mut().take(players) {
+                *slot_actions = ActionSet(u16::from_le_bytes(reader.array()?));
+            }
+            let delta = Duration::from_nanos(u32::from_le_bytes(reader.array()?) as u64);
+            frames.push(ReplayFrame { actions, delta });
+        }
+
+        Some(Replay {
+            seed,
+            difficulty,
+            players,
+            versus,
+            gravity,
+            arena,
+            bosses,
+            chicken_chance,
+            hazards,
+            enemies_hash,
+            window_size,
+            recorded_at,
+            frames,
+        })
+    }
+}
+
+// Also used to read network messages, see `net`
+pub struct ByteReader<'a>(pub &'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    pub fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+}
+
+pub enum ReplayMode {
+    Idle,
+    Recording(Replay),
+    // Waiting for the `RestartRun` that starts the playback
+    Starting(Replay),
+    Playing { replay: Replay, frame: usize },
+}
+
+pub struct ReplayState {
+    pub mode: ReplayMode,
+}
+
+impl ReplayState {
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, ReplayMode::Starting(_) | ReplayMode::Playing { .. })
+    }
+}
+
+// Starts playing a replay from wherever the game currently is
+pub struct PlayReplay(pub Replay);
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ReplayState { mode: ReplayMode::Idle })
+            .init_resource::<GameTick>()
+            .add_event::<PlayReplay>()
+            .add_startup_system(play_command_line_replay)
+            .add_system(start_playback)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                begin_run.label("begin_run").after("reseed_random_generator"),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                advance_tick
+                    .label("advance_tick")
+                    .after(InputSystem)
+                    .after("track_gamepads")
+                    .after("begin_run")
+                    .after("apply_pause"),
+            )
+            // Online runs depend on other players, so they can't be replayed
+            .add_system(finish_run.with_run_criteria(offline));
+    }
+}
+
+// `--replay <path>` plays a replay as soon as the game opens
+fn play_command_line_replay(mut play_events: EventWriter<PlayReplay>) {
+    if let Some(path) = command_line_value("--replay") {
+        match std::fs::read(&path).ok().and_then(|bytes| Replay::from_bytes(&bytes)) {
+            Some(replay) => play_events.send(PlayReplay(replay)),
+            None => warn!("Could not load replay {}", path),
+        }
+    }
+}
+
+// A replay waits for the enemy types to load, so they can be checked against the ones it was recorded with
+fn start_playback(
+    mut play_events: EventReader<PlayReplay>,
+    mut waiting: Local<Option<Replay>>,
+    (enemies, registries): (Res<Enemies>, Res<Assets<EnemyRegistry>>),
+    mut state: ResMut<ReplayState>,
+    mut next_seed: ResMut<NextRunSeed>,
+    mut difficulty: ResMut<Difficulty>,
+    mut player_count: ResMut<PlayerCount>,
+    mut versus: ResMut<Versus>,
+    mut gravity: ResMut<Gravity>,
+    mut arena: ResMut<Arena>,
+    (mut bosses, mut chicken_settings, mut hazards): (ResMut<Bosses>, ResMut<ChickenSettings>, ResMut<Hazards>),
+    mut in_menu: ResMut<InMenu>,
+    mut restart_events: EventWriter<RestartRun>,
+    windows: Res<Windows>,
+) {
+    if let Some(PlayReplay(replay)) = play_events.iter().last() {
+        *waiting = Some(replay.clone());
+    }
+    let registry = match enemies.loaded(&registries) {
+        Some(registry) => registry,
+        None => return,
+    };
+
+    if let Some(replay) = waiting.take() {
+        if replay.enemies_hash != registry.hash {
+            warn!("Replay was recorded with different enemy types than the ones loaded, so it can't be played");
+            return;
+        }
+
+        let window = windows.get_primary().unwrap();
+        if (window.width(), window.height()) != replay.window_size {
+            warn!(
+                "Replay was recorded in a {}x{} window, this one is {}x{}. It will probably play out differently.",
+                replay.window_size.0,
+                replay.window_size.1,
+                window.width(),
+                window.height(),
+            );
+        }
+
+        next_seed.0 = Some(replay.seed);
+        *difficulty = replay.difficulty;
+        player_count.0 = replay.players;
+        versus.enabled = replay.versus;
+        gravity.enabled = replay.gravity;
+        arena.shape = replay.arena.map(|(shape, _)| shape);
+        if let Some((_, size)) = replay.arena {
+            arena.size = size;
+        }
+        bosses.enabled = replay.bosses;
+        chicken_settings.spawn_chance = replay.chicken_chance;
+        hazards.enabled = replay.hazards;
+        in_menu.0 = false;
+        state.mode = ReplayMode::Starting(replay);
+        restart_events.send(RestartRun);
+    }
+}
+
+// Every run is recorded unless it is a replay being played back
+fn begin_run(
+    mut restart_events: EventReader<RestartRun>,
+    mut state: ResMut<ReplayState>,
+    run_seed: Res<RunSeed>,
+    difficulty: Res<Difficulty>,
+    player_count: Res<PlayerCount>,
+    versus: Res<Versus>,
+    gravity: Res<Gravity>,
+    arena: Res<Arena>,
+    (bosses, chicken_settings, hazards): (Res<Bosses>, Res<ChickenSettings>, Res<Hazards>),
+    windows: Res<Windows>,
+) {
+    if restart_events.iter().count() == 0 {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+
+    let mode = std::mem::replace(&mut state.mode, ReplayMode::Idle);
+    state.mode = match mode {
+        ReplayMode::Starting(replay) => ReplayMode::Playing { replay, frame: 0 },
+        _ => ReplayMode::Recording(Replay {
+            seed: run_seed.0,
+            difficulty: *difficulty,
+            players: player_count.0,
+            versus: versus.enabled,
+            gravity: gravity.enabled,
+            arena: arena.shape.map(|shape| (shape, arena.size)),
+            bosses: bosses.enabled,
+            chicken_chance: chicken_settings.spawn_chance,
+            hazards: hazards.enabled,
+            window_size: (window.width(), window.height()),
+            // Both set when the replay is saved
+            enemies_hash: 0,
+            recorded_at: 0,
+            frames: Vec::new(),
+        }),
+    };
+}
+
+fn advance_tick(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    (gamepads, gamepad_buttons, gamepad_axes): (Res<ConnectedGamepads>, Res<Input<GamepadButton>>, Res<Axis<GamepadAxis>>),
+    player_count: Res<PlayerCount>,
+    settings: Res<Settings>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    in_menu: Res<InMenu>,
+    mut state: ResMut<ReplayState>,
+    mut tick: ResMut<GameTick>,
+    mut physics_steps: ResMut<PhysicsSteps>,
+    (enemies, registries): (Res<Enemies>, Res<Assets<EnemyRegistry>>),
+) {
+    // Loading takes however long it takes, so nothing moves until the enemy types are
+    // there rather than the first spawns quietly going missing
+    let loaded = enemies.loaded(&registries).is_some();
+    let running = !game_paused.0 && !player_died.0 && !in_menu.0 && loaded;
+
+    let frame = if !running {
+        ReplayFrame {
+            actions: [ActionSet::default(); MAX_PLAYERS],
+            delta: Duration::ZERO,
+        }
+    } else {
+        match &mut state.mode {
+            ReplayMode::Playing { replay, frame } if *frame < replay.frames.len() => {
+                *frame += 1;
+                replay.frames[*frame - 1]
+            }
+            mode => {
+                // Out of frames, so the player takes over from here
+                if let ReplayMode::Playing { .. } = mode {
+                    *mode = ReplayMode::Idle;
+                }
+
+                let mut actions = [ActionSet::default(); MAX_PLAYERS];
+                for (slot, slot_actions) in actions.iter_mut().enumerate().take(player_count.0) {
+                    *slot_actions = player_actions(
+                        slot,
+                        player_count.0,
+                        &settings.key_bindings,
+                        &keyboard_input,
+                        &gamepads,
+                        &gamepad_buttons,
+                        &gamepad_axes,
+                    );
+                }
+
+                let frame = ReplayFrame {
+                    actions,
+                    delta: time.delta().min(MAX_TICK),
+                };
+
+                if let ReplayMode::Recording(replay) = mode {
+                    replay.frames.push(frame);
+                }
+
+                frame
+            }
+        }
+    };
+
+    *tick = GameTick {
+        running,
+        delta: frame.delta,
+        actions: frame.actions,
+    };
+    *physics_steps = PhysicsSteps::every_frame(frame.delta);
+}
+
+// Saves the run when the player dies. A replay that was being watched stays in
+// `Playing` until the next run, so the death screen knows it was a replay.
+fn finish_run(
+    player_died: Res<PlayerDied>,
+    cheats_used: Res<CheatsUsed>,
+    (enemies, registries): (Res<Enemies>, Res<Assets<EnemyRegistry>>),
+    mut state: ResMut<ReplayState>,
+) {
+    if !player_died.is_changed() || !player_died.0 {
+        return;
+    }
+
+    // Console commands aren't in the recorded input, so a run with cheats wouldn't play back the same
+    if let ReplayMode::Recording(replay) = &mut state.mode {
+        if !replay.frames.is_empty() && !cheats_used.this_run {
+            replay.recorded_at = unix_time();
+            replay.enemies_hash = enemies.loaded(&registries).map_or(0, |registry| registry.hash);
+            save_replay(replay);
+        }
+        state.mode = ReplayMode::Idle;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_replay(replay: &Replay) {
+    let folder = match crate::storage::folder("replays") {
+        Some(folder) => folder,
+        None => return,
+    };
+
+    // Runs can end within the same second, so the first free number goes on the end
+    let path = (0..)
+        .map(|n| folder.join(format!("{}-{}.replay", replay.recorded_at, n)))
+        .find(|path| !path.exists())
+        .unwrap();
+    let result = std::fs::create_dir_all(&folder).and_then(|_| std::fs::write(&path, replay.to_bytes()));
+    if let Err(e) = result {
+        warn!("Could not save replay {}: {}", path.display(), e);
+        return;
+    }
+
+    // Only the most recent runs are kept
+    for (old_path, _) in list_replays().into_iter().skip(MAX_SAVED_REPLAYS) {
+        let _ = std::fs::remove_file(old_path);
+    }
+}
+
+// The web build has nowhere to keep replay files, see `storage`
+#[cfg(target_arch = "wasm32")]
+fn save_replay(_replay: &Replay) {}
+
+// Saved replays, newest first
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_replays() -> Vec<(std::path::PathBuf, Replay)> {
+    let entries = match crate::storage::folder("replays").and_then(|folder| std::fs::read_dir(folder).ok()) {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+
+    let mut replays: Vec<(std::path::PathBuf, Replay)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "replay"))
+        .filter_map(|path| {
+            let replay = Replay::from_bytes(&std::fs::read(&path).ok()?)?;
+            Some((path, replay))
+        })
+        .collect();
+
+    replays.sort_by(|a, b| b.1.recorded_at.cmp(&a.1.recorded_at));
+    replays
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn list_replays() -> Vec<(std::path::PathBuf, Replay)> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        let mut actions = [ActionSet::default(); MAX_PLAYERS];
+        actions[0] = ActionSet(0b1010);
+        actions[1] = ActionSet(0x1ff);
+        Replay {
+            seed: 0xDEAD_BEEF_1234,
+            difficulty: Difficulty::Hard,
+            players: 2,
+            versus: true,
+            gravity: true,
+            arena: Some((ArenaShape::Circle, 3.5)),
+            bosses: false,
+            chicken_chance: 0.25,
+            hazards: false,
+            enemies_hash: 42,
+            window_size: (1280., 720.),
+            recorded_at: 1_760_000_000,
+            frames: vec![
+                ReplayFrame { actions, delta: Duration::from_millis(16) },
+                ReplayFrame { actions: [ActionSet::default(); MAX_PLAYERS], delta: Duration::from_nanos(123_456) },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let original = replay();
+        let read = Replay::from_bytes(&original.to_bytes()).unwrap();
+
+        assert_eq!(read.seed, original.seed);
+        assert_eq!(read.difficulty, original.difficulty);
+        assert_eq!(read.players, original.players);
+        assert_eq!(read.versus, original.versus);
+        assert_eq!(read.gravity, original.gravity);
+        assert_eq!(read.arena, original.arena);
+        assert_eq!(read.bosses, original.bosses);
+        assert_eq!(read.chicken_chance, original.chicken_chance);
+        assert_eq!(read.hazards, original.hazards);
+        assert_eq!(read.enemies_hash, original.enemies_hash);
+        assert_eq!(read.window_size, original.window_size);
+        assert_eq!(read.recorded_at, original.recorded_at);
+        assert_eq!(read.frames.len(), original.frames.len());
+        for (read, original) in read.frames.iter().zip(original.frames.iter()) {
+            assert_eq!(read.actions, original.actions);
+            assert_eq!(read.delta, original.delta);
+        }
+    }
+
+    #[test]
+    fn round_trips_open_space() {
+        let original = Replay { arena: None, players: 1, ..replay() };
+        let read = Replay::from_bytes(&original.to_bytes()).unwrap();
+        assert_eq!(read.arena, None);
+        assert_eq!(read.players, 1);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = replay().to_bytes();
+        bytes[4] = VERSION + 1;
+        assert!(Replay::from_bytes(&bytes).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = replay().to_bytes();
+        for length in 0..bytes.len() {
+            assert!(Replay::from_bytes(&bytes[..length]).is_none(), "read {} bytes", length);
+        }
+    }
+}
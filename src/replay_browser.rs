@@ -0,0 +1,209 @@
+use std::path::PathBuf;
+
+use bevy::{input::InputSystem, prelude::*};
+
+use crate::{
+    date::format_timestamp,
+    replay::{list_replays, PlayReplay, Replay},
+    BoldFont,
+};
+
+// Lists the saved replays from the main menu, newest first. Works like the settings
+// menu: Up/Down to pick, Enter to watch, Escape to go back, and every key it sees is
+// swallowed so the main menu underneath doesn't react too.
+
+// Only this many rows fit on screen, the list scrolls to keep the selection visible
+const VISIBLE_ROWS: usize = 10;
+
+// Sent by the main menu
+pub struct OpenReplays;
+
+#[derive(Default)]
+pub struct ReplayBrowser {
+    pub open: bool,
+    selected: usize,
+    entries: Vec<(PathBuf, Replay)>,
+}
+
+pub struct ReplayBrowserPlugin;
+
+impl Plugin for ReplayBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ReplayBrowser>()
+            .add_event::<OpenReplays>()
+            .add_system(open_replay_browser.label("open_replay_browser"))
+            .add_system(sync_replay_browser.after("open_replay_browser"))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                replay_browser_input.after(InputSystem).after("console_input"),
+            )
+            .add_system(update_replay_browser_text);
+    }
+}
+
+#[derive(Component)]
+struct ReplayBrowserRoot;
+
+#[derive(Component)]
+struct ReplayBrowserText;
+
+fn open_replay_browser(mut events: EventReader<OpenReplays>, mut browser: ResMut<ReplayBrowser>) {
+    if events.iter().count() > 0 && !browser.open {
+        *browser = ReplayBrowser {
+            open: true,
+            selected: 0,
+            entries: list_replays(),
+        };
+    }
+}
+
+fn sync_replay_browser(
+    mut commands: Commands,
+    browser: Res<ReplayBrowser>,
+    bold_font: Res<BoldFont>,
+    root_query: Query<Entity, With<ReplayBrowserRoot>>,
+) {
+    let spawned = root_query.iter().next().is_some();
+
+    if browser.open && !spawned {
+        spawn_replay_browser(&mut commands, bold_font.0.clone());
+    } else if !browser.open && spawned {
+        root_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    }
+}
+
+fn spawn_replay_browser(commands: &mut Commands, font: Handle<Font>) {
+    let centered = TextAlignment {
+        horizontal: HorizontalAlign::Center,
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            ..Default::default()
+        })
+        .insert(ReplayBrowserRoot)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Replays",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 64.0,
+                        color: Color::WHITE,
+                    },
+                    centered,
+                ),
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 26.0,
+                            color: Color::WHITE,
+                        },
+                        centered,
+                    ),
+                    ..Default::default()
+                })
+                .insert(ReplayBrowserText);
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Up/Down to select, Enter to watch, Escape to go back",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: Color::GRAY,
+                    },
+                    centered,
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn replay_browser_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut browser: ResMut<ReplayBrowser>,
+    mut play_events: EventWriter<PlayReplay>,
+) {
+    if !browser.open {
+        return;
+    }
+
+    let count = browser.entries.len();
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        browser.open = false;
+    } else if count > 0 {
+        if keyboard_input.just_pressed(KeyCode::Up) {
+            browser.selected = (browser.selected + count - 1) % count;
+        }
+        if keyboard_input.just_pressed(KeyCode::Down) {
+            browser.selected = (browser.selected + 1) % count;
+        }
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            play_events.send(PlayReplay(browser.entries[browser.selected].1.clone()));
+            browser.open = false;
+        }
+    }
+
+    let pressed: Vec<KeyCode> = keyboard_input.get_just_pressed().copied().collect();
+    for key in pressed {
+        keyboard_input.reset(key);
+    }
+}
+
+fn update_replay_browser_text(browser: Res<ReplayBrowser>, mut text_query: Query<&mut Text, With<ReplayBrowserText>>) {
+    if !browser.open {
+        return;
+    }
+
+    let value = if browser.entries.is_empty() {
+        String::from("\nNo replays yet, every run you finish is saved here\n")
+    } else {
+        let first = browser.selected.saturating_sub(VISIBLE_ROWS - 1);
+        let rows: Vec<String> = browser
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE_ROWS)
+            .map(|(index, (_, replay))| {
+                let row = format!(
+                    "{}   {}{}{}{}   {:.1}s",
+                    format_timestamp(replay.recorded_at),
+                    replay.difficulty.name(),
+                    match (replay.players, replay.versus) {
+                        (_, true) => " versus",
+                        (1, false) => "",
+                        _ => " co-op",
+                    },
+                    if replay.gravity { " orbital" } else { "" },
+                    if replay.arena.is_some() { " arena" } else { "" },
+                    replay.duration().as_secs_f32(),
+                );
+                if index == browser.selected { format!("> {} <", row) } else { row }
+            })
+            .collect();
+        format!("\n{}\n", rows.join("\n"))
+    };
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
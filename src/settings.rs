@@ -0,0 +1,156 @@
+use bevy::{prelude::*, window::WindowMode};
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::CameraSettings, controls::KeyBindings, particles::ParticleSettings, storage};
+
+const SETTINGS_KEY: &str = "settings";
+
+pub const RESOLUTIONS: [(f32, f32); 5] = [
+    (1200., 800.),
+    (1280., 720.),
+    (1600., 900.),
+    (1920., 1080.),
+    (2560., 1440.),
+];
+
+// What the particle cap steps through in the settings menu. The default for the
+// platform, see `ParticleSettings`, is one of these.
+pub const PARTICLE_LIMITS: [usize; 6] = [0, 200, 400, 800, 1500, 3000];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum DisplayMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl DisplayMode {
+    pub fn window_mode(&self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayMode::Windowed => "Windowed",
+            DisplayMode::Borderless => "Borderless",
+            DisplayMode::Fullscreen => "Fullscreen",
+        }
+    }
+
+    pub fn next(&self) -> DisplayMode {
+        match self {
+            DisplayMode::Windowed => DisplayMode::Borderless,
+            DisplayMode::Borderless => DisplayMode::Fullscreen,
+            DisplayMode::Fullscreen => DisplayMode::Windowed,
+        }
+    }
+}
+
+// Loaded in `main` before the window is created, and saved every time it changes.
+// Missing fields fall back to their defaults so old settings files keep loading.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub display_mode: DisplayMode,
+    pub resolution: (f32, f32),
+    pub vsync: bool,
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub show_fps: bool,
+    pub screen_shake: f32,
+    pub show_ghost: bool,
+    // Turning this down is the first thing to try on a slow machine or browser
+    pub max_particles: usize,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            display_mode: DisplayMode::Windowed,
+            resolution: RESOLUTIONS[0],
+            vsync: true,
+            master_volume: 1.,
+            music_volume: 0.7,
+            sfx_volume: 0.8,
+            show_fps: false,
+            screen_shake: 1.,
+            show_ghost: true,
+            max_particles: ParticleSettings::default().max_particles,
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        storage::load(SETTINGS_KEY)
+            .and_then(|contents| ron::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => storage::save(SETTINGS_KEY, &contents),
+            Err(e) => warn!("Could not serialize settings: {}", e),
+        }
+    }
+
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        WindowDescriptor {
+            mode: self.display_mode.window_mode(),
+            width: self.resolution.0,
+            height: self.resolution.1,
+            vsync: self.vsync,
+            ..Default::default()
+        }
+    }
+}
+
+// What was last pushed to the window, so a volume change doesn't also undo a manual resize
+#[derive(Default)]
+pub struct AppliedSettings {
+    display_mode: Option<DisplayMode>,
+    resolution: Option<(f32, f32)>,
+    vsync: Option<bool>,
+}
+
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut applied: Local<AppliedSettings>,
+    mut windows: ResMut<Windows>,
+    mut camera_settings: ResMut<CameraSettings>,
+    mut particle_settings: ResMut<ParticleSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let window = windows.get_primary_mut().unwrap();
+
+    if applied.display_mode != Some(settings.display_mode) {
+        window.set_mode(settings.display_mode.window_mode());
+        applied.display_mode = Some(settings.display_mode);
+    }
+    if applied.resolution != Some(settings.resolution) {
+        // The window already opened at this resolution, see `main`
+        if applied.resolution.is_some() {
+            window.set_resolution(settings.resolution.0, settings.resolution.1);
+        }
+        applied.resolution = Some(settings.resolution);
+    }
+    if applied.vsync != Some(settings.vsync) {
+        window.set_vsync(settings.vsync);
+        applied.vsync = Some(settings.vsync);
+    }
+
+    camera_settings.shake_intensity = settings.screen_shake;
+    particle_settings.max_particles = settings.max_particles;
+
+    settings.save();
+}
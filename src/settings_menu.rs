@@ -0,0 +1,313 @@
+use bevy::{input::InputSystem, prelude::*};
+
+use crate::{
+    controls::InputAction,
+    pause_menu::OpenSettings,
+    settings::{Settings, PARTICLE_LIMITS, RESOLUTIONS},
+    BoldFont,
+};
+
+// A keyboard driven list: Up/Down picks a row, Left/Right changes it, Enter rebinds
+// keys, Escape goes back to whichever screen opened it. Input is handled right after
+// bevy updates `Input<KeyCode>` and every key it sees is swallowed, so nothing else
+// (pausing, the main menu, the camera) reacts to keys pressed in this menu.
+
+const VOLUME_STEP: f32 = 0.1;
+const SHAKE_STEP: f32 = 0.25;
+
+#[derive(Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    selected: usize,
+    rebinding: bool,
+}
+
+pub struct SettingsMenuPlugin;
+
+impl Plugin for SettingsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SettingsMenu>()
+            .add_system(open_settings_menu.label("open_settings_menu"))
+            .add_system(sync_settings_menu.after("open_settings_menu"))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                settings_menu_input.after(InputSystem).after("console_input"),
+            )
+            .add_system(update_settings_text);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SettingsRow {
+    DisplayMode,
+    Resolution,
+    VSync,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    ShowFps,
+    ScreenShake,
+    ShowGhost,
+    Particles,
+    Binding(InputAction),
+    Back,
+}
+
+fn rows() -> Vec<SettingsRow> {
+    let mut rows = vec![
+        SettingsRow::DisplayMode,
+        SettingsRow::Resolution,
+        SettingsRow::VSync,
+        SettingsRow::MasterVolume,
+        SettingsRow::MusicVolume,
+        SettingsRow::SfxVolume,
+        SettingsRow::ShowFps,
+        SettingsRow::ScreenShake,
+        SettingsRow::ShowGhost,
+        SettingsRow::Particles,
+    ];
+    rows.extend(InputAction::ALL.iter().map(|action| SettingsRow::Binding(*action)));
+    rows.push(SettingsRow::Back);
+    rows
+}
+
+#[derive(Component)]
+struct SettingsMenuRoot;
+
+#[derive(Component)]
+struct SettingsRowText(usize);
+
+fn open_settings_menu(mut events: EventReader<OpenSettings>, mut menu: ResMut<SettingsMenu>) {
+    if events.iter().count() > 0 && !menu.open {
+        *menu = SettingsMenu {
+            open: true,
+            ..Default::default()
+        };
+    }
+}
+
+fn sync_settings_menu(
+    mut commands: Commands,
+    menu: Res<SettingsMenu>,
+    bold_font: Res<BoldFont>,
+    root_query: Query<Entity, With<SettingsMenuRoot>>,
+) {
+    let spawned = root_query.iter().next().is_some();
+
+    if menu.open && !spawned {
+        spawn_settings_menu(&mut commands, bold_font.0.clone());
+    } else if !menu.open && spawned {
+        root_query.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    }
+}
+
+fn spawn_settings_menu(commands: &mut Commands, font: Handle<Font>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            ..Default::default()
+        })
+        .insert(SettingsMenuRoot)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Settings",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 64.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            for index in 0..rows().len() {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "",
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 26.0,
+                                color: Color::WHITE,
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(SettingsRowText(index));
+            }
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Up/Down to select, Left/Right to change, Enter to rebind, Escape to go back",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: Color::GRAY,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn settings_menu_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+) {
+    if !menu.open {
+        return;
+    }
+
+    // Edit a copy so `Settings` is only marked changed (and saved) when something really changed
+    let mut edited = settings.clone();
+    handle_settings_keys(&keyboard_input, &mut menu, &mut edited);
+    if edited != *settings {
+        *settings = edited;
+    }
+
+    let pressed: Vec<KeyCode> = keyboard_input.get_just_pressed().copied().collect();
+    for key in pressed {
+        keyboard_input.reset(key);
+    }
+}
+
+fn handle_settings_keys(keyboard_input: &Input<KeyCode>, menu: &mut SettingsMenu, settings: &mut Settings) {
+    let rows = rows();
+    let row = rows[menu.selected];
+
+    if menu.rebinding {
+        if let Some(key) = keyboard_input.get_just_pressed().next().copied() {
+            if let SettingsRow::Binding(action) = row {
+                if key != KeyCode::Escape {
+                    settings.key_bindings.get_mut(action).primary = key;
+                }
+            }
+            menu.rebinding = false;
+        }
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        menu.open = false;
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + rows.len() - 1) % rows.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % rows.len();
+    }
+
+    let left = keyboard_input.just_pressed(KeyCode::Left);
+    let right = keyboard_input.just_pressed(KeyCode::Right);
+    let enter = keyboard_input.just_pressed(KeyCode::Return);
+    let direction = if right { 1. } else if left { -1. } else { 0. };
+
+    match row {
+        SettingsRow::DisplayMode if left || right || enter => {
+            settings.display_mode = settings.display_mode.next();
+        }
+        SettingsRow::Resolution if left || right => {
+            let current = RESOLUTIONS.iter().position(|r| *r == settings.resolution).unwrap_or(0);
+            let next = if right {
+                (current + 1) % RESOLUTIONS.len()
+            } else {
+                (current + RESOLUTIONS.len() - 1) % RESOLUTIONS.len()
+            };
+            settings.resolution = RESOLUTIONS[next];
+        }
+        SettingsRow::VSync if left || right || enter => settings.vsync = !settings.vsync,
+        SettingsRow::MasterVolume if direction != 0. => {
+            settings.master_volume = step(settings.master_volume, direction * VOLUME_STEP, 1.);
+        }
+        SettingsRow::MusicVolume if direction != 0. => {
+            settings.music_volume = step(settings.music_volume, direction * VOLUME_STEP, 1.);
+        }
+        SettingsRow::SfxVolume if direction != 0. => {
+            settings.sfx_volume = step(settings.sfx_volume, direction * VOLUME_STEP, 1.);
+        }
+        SettingsRow::ShowFps if left || right || enter => settings.show_fps = !settings.show_fps,
+        SettingsRow::ScreenShake if direction != 0. => {
+            settings.screen_shake = step(settings.screen_shake, direction * SHAKE_STEP, 2.);
+        }
+        SettingsRow::ShowGhost if left || right || enter => settings.show_ghost = !settings.show_ghost,
+        // Stops at either end rather than wrapping, like the volumes
+        SettingsRow::Particles if left || right => {
+            let last = PARTICLE_LIMITS.len() - 1;
+            let current = PARTICLE_LIMITS.iter().position(|l| *l >= settings.max_particles).unwrap_or(last);
+            let next = if right { (current + 1).min(last) } else { current.saturating_sub(1) };
+            settings.max_particles = PARTICLE_LIMITS[next];
+        }
+        SettingsRow::Binding(_) if enter => menu.rebinding = true,
+        SettingsRow::Back if enter => menu.open = false,
+        _ => {}
+    }
+}
+
+// Rounded so repeated steps don't drift away from nice values
+fn step(value: f32, amount: f32, max: f32) -> f32 {
+    ((value + amount).clamp(0., max) * 100.).round() / 100.
+}
+
+fn update_settings_text(
+    menu: Res<SettingsMenu>,
+    settings: Res<Settings>,
+    mut text_query: Query<(&SettingsRowText, &mut Text)>,
+) {
+    if !menu.open {
+        return;
+    }
+
+    let rows = rows();
+    let on_off = |value: bool| if value { "On" } else { "Off" };
+    let percent = |value: f32| format!("{:.0}%", value * 100.);
+
+    for (SettingsRowText(index), mut text) in text_query.iter_mut() {
+        let row = rows[*index];
+        let selected = *index == menu.selected;
+
+        let value = match row {
+            SettingsRow::DisplayMode => format!("Window Mode: {}", settings.display_mode.name()),
+            SettingsRow::Resolution => format!("Resolution: {}x{}", settings.resolution.0, settings.resolution.1),
+            SettingsRow::VSync => format!("VSync: {}", on_off(settings.vsync)),
+            SettingsRow::MasterVolume => format!("Master Volume: {}", percent(settings.master_volume)),
+            SettingsRow::MusicVolume => format!("Music Volume: {}", percent(settings.music_volume)),
+            SettingsRow::SfxVolume => format!("Sound Effects Volume: {}", percent(settings.sfx_volume)),
+            SettingsRow::ShowFps => format!("Show FPS: {}", on_off(settings.show_fps)),
+            SettingsRow::ScreenShake => format!("Screen Shake: {}", percent(settings.screen_shake)),
+            SettingsRow::ShowGhost => format!("Best Run Ghost: {}", on_off(settings.show_ghost)),
+            SettingsRow::Particles if settings.max_particles == 0 => String::from("Particles: Off"),
+            SettingsRow::Particles => format!("Particles: {}", settings.max_particles),
+            SettingsRow::Binding(action) => {
+                let binding = settings.key_bindings.get(action);
+                if selected && menu.rebinding {
+                    format!("{}: press a key...", action.name())
+                } else {
+                    match binding.secondary {
+                        Some(secondary) => format!("{}: {:?} / {:?}", action.name(), binding.primary, secondary),
+                        None => format!("{}: {:?}", action.name(), binding.primary),
+                    }
+                }
+            }
+            SettingsRow::Back => String::from("Back"),
+        };
+
+        text.sections[0].value = if selected { format!("> {} <", value) } else { value };
+        text.sections[0].style.color = if selected { Color::GOLD } else { Color::WHITE };
+    }
+}
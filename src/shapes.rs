@@ -0,0 +1,68 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+const SHAPE_SIZE: u32 = 64;
+
+// Simple shapes drawn at startup, so UI and effects that just need a tinted
+// arrow or dot don't each need their own image file
+pub struct GeneratedShapes {
+    pub arrow: Handle<Image>,
+    pub circle: Handle<Image>,
+    // Outlines only, for debug drawing over sprites
+    pub ring: Handle<Image>,
+    pub square_outline: Handle<Image>,
+}
+
+pub fn create_shapes(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    // Arrow pointing up (+Y), with the tip at the top edge of the image
+    let arrow = draw_shape(|x, y| {
+        let half_width = (1.0 - y) * 0.5;
+        (x - 0.5).abs() <= half_width && y > 0.15
+    });
+
+    let circle = draw_shape(|x, y| {
+        let (dx, dy) = (x - 0.5, y - 0.5);
+        dx * dx + dy * dy <= 0.25
+    });
+
+    let ring = draw_shape(|x, y| {
+        let distance = ((x - 0.5).powi(2) + (y - 0.5).powi(2)).sqrt();
+        (0.46..=0.5).contains(&distance)
+    });
+
+    let square_outline = draw_shape(|x, y| x.min(y).min(1.0 - x).min(1.0 - y) <= 0.04);
+
+    commands.insert_resource(GeneratedShapes {
+        arrow: images.add(arrow),
+        circle: images.add(circle),
+        ring: images.add(ring),
+        square_outline: images.add(square_outline),
+    });
+}
+
+// `inside` receives pixel centers in [0, 1] with y pointing up
+fn draw_shape(inside: impl Fn(f32, f32) -> bool) -> Image {
+    let mut data = Vec::with_capacity((SHAPE_SIZE * SHAPE_SIZE * 4) as usize);
+
+    for row in 0..SHAPE_SIZE {
+        for column in 0..SHAPE_SIZE {
+            let x = (column as f32 + 0.5) / SHAPE_SIZE as f32;
+            let y = 1.0 - (row as f32 + 0.5) / SHAPE_SIZE as f32;
+            let alpha = if inside(x, y) { 255 } else { 0 };
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: SHAPE_SIZE,
+            height: SHAPE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
@@ -0,0 +1,154 @@
+use bevy::{prelude::*, window::WindowResized, app::Events};
+
+use crate::{camera::CameraView, RunSeed};
+
+// Each layer is an infinite grid of cells. Only the cells around the camera are ever
+// spawned, as a fixed pool of sprites that get moved to whichever cell they now cover.
+// Star positions inside a cell come from hashing the run seed with the cell coordinates,
+// so a cell always looks the same when the player comes back to it.
+// Layers are laid out in screen pixels and ignore camera zoom, since they are meant to be far away.
+struct StarLayer {
+    parallax: f32,
+    cell_size: f32,
+    stars_per_cell: u64,
+    min_size: f32,
+    max_size: f32,
+    brightness: f32,
+    z: f32,
+}
+
+// Sprites and meteors sit at z = 0 and the camera clips anything below -0.1,
+// so the layers have to squeeze in just behind them
+const LAYERS: [StarLayer; 3] = [
+    StarLayer { parallax: 0.1, cell_size: 160., stars_per_cell: 3, min_size: 1., max_size: 2., brightness: 0.45, z: -0.09 },
+    StarLayer { parallax: 0.3, cell_size: 220., stars_per_cell: 2, min_size: 1.5, max_size: 3., brightness: 0.7, z: -0.07 },
+    StarLayer { parallax: 0.6, cell_size: 320., stars_per_cell: 2, min_size: 2., max_size: 4., brightness: 1.0, z: -0.05 },
+];
+
+pub struct StarfieldPlugin;
+
+impl Plugin for StarfieldPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(StarfieldPool { seed: None, width: 0., height: 0. })
+            .add_system(rebuild_starfield.label("rebuild_starfield"))
+            .add_system(scroll_starfield.after("rebuild_starfield").after("camera"));
+    }
+}
+
+#[derive(Component)]
+struct Star {
+    layer: usize,
+    slot_x: i32,
+    slot_y: i32,
+    index: u64,
+}
+
+// What the current pool of star sprites was built for
+struct StarfieldPool {
+    seed: Option<u64>,
+    width: f32,
+    height: f32,
+}
+
+fn slots_for(layer: &StarLayer, width: f32, height: f32) -> (i32, i32) {
+    (
+        (width / layer.cell_size).ceil() as i32 + 1,
+        (height / layer.cell_size).ceil() as i32 + 1,
+    )
+}
+
+fn rebuild_starfield(
+    mut commands: Commands,
+    mut pool: ResMut<StarfieldPool>,
+    run_seed: Res<RunSeed>,
+    windows: Res<Windows>,
+    resize_event: Res<Events<WindowResized>>,
+    star_query: Query<Entity, With<Star>>,
+) {
+    let mut reader = resize_event.get_reader();
+    let resized = reader.iter(&resize_event).next().is_some();
+
+    if pool.seed == Some(run_seed.0) && !resized {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    pool.seed = Some(run_seed.0);
+    pool.width = window.width();
+    pool.height = window.height();
+
+    star_query.iter().for_each(|e| commands.entity(e).despawn());
+
+    for (layer_index, layer) in LAYERS.iter().enumerate() {
+        let (cols, rows) = slots_for(layer, pool.width, pool.height);
+        for slot_x in 0..cols {
+            for slot_y in 0..rows {
+                for index in 0..layer.stars_per_cell {
+                    commands
+                        .spawn_bundle(SpriteBundle {
+                            transform: Transform::from_xyz(0.0, 0.0, layer.z),
+                            ..Default::default()
+                        })
+                        .insert(Star { layer: layer_index, slot_x, slot_y, index });
+                }
+            }
+        }
+    }
+}
+
+fn scroll_starfield(
+    pool: Res<StarfieldPool>,
+    view: Res<CameraView>,
+    mut star_query: Query<(&Star, &mut Transform, &mut Sprite)>,
+) {
+    let seed = match pool.seed {
+        Some(seed) => seed,
+        None => return,
+    };
+
+    let camera = view.center;
+    let half_extents = Vec2::new(pool.width, pool.height) / 2.;
+
+    for (star, mut transform, mut sprite) in star_query.iter_mut() {
+        let layer = &LAYERS[star.layer];
+
+        // Where the camera is in this layer's coordinates
+        let center = camera * layer.parallax;
+        let first_cell = ((center - half_extents) / layer.cell_size).floor();
+        let cell_x = first_cell.x as i32 + star.slot_x;
+        let cell_y = first_cell.y as i32 + star.slot_y;
+
+        let hash = cell_hash(seed, star.layer as u64, cell_x, cell_y, star.index);
+        let local = Vec2::new(unit(hash), unit(mix(hash))) * layer.cell_size;
+        let layer_position = Vec2::new(cell_x as f32, cell_y as f32) * layer.cell_size + local;
+        let world_position = camera + (layer_position - center) * view.zoom;
+
+        transform.translation = world_position.extend(layer.z);
+
+        let size = (layer.min_size + (layer.max_size - layer.min_size) * unit(mix(mix(hash)))) * view.zoom;
+        let brightness = layer.brightness * (0.6 + 0.4 * unit(mix(mix(mix(hash)))));
+        sprite.custom_size = Some(Vec2::new(size, size));
+        sprite.color = Color::rgba(brightness, brightness, brightness * 1.1, 1.0);
+    }
+}
+
+// splitmix64, which is plenty for scattering stars around
+pub fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn cell_hash(seed: u64, layer: u64, x: i32, y: i32, index: u64) -> u64 {
+    let mut hash = mix(seed ^ layer);
+    hash = mix(hash ^ x as u32 as u64);
+    hash = mix(hash ^ y as u32 as u64);
+    mix(hash ^ index)
+}
+
+// Maps a hash to [0, 1)
+fn unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
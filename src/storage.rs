@@ -0,0 +1,58 @@
+// Small key/value persistence. Native builds write one file per key in a `saves`
+// folder next to the executable, and the web build uses the browser's local storage.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path_for(key: &str) -> Option<std::path::PathBuf> {
+    Some(folder("")?.join(format!("{}.ron", key)))
+}
+
+// A folder inside `saves` for things that don't fit in a single key, like replays.
+// Only native builds have one.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn folder(name: &str) -> Option<std::path::PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join("saves").join(name))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(key: &str) -> Option<String> {
+    std::fs::read_to_string(path_for(key)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(key: &str, contents: &str) {
+    let path = match path_for(key) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, contents));
+
+    if let Err(e) = result {
+        bevy::log::warn!("Could not save {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(key: &str) -> Option<String> {
+    local_storage()?.get_item(&format!("earth_escape.{}", key)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(key: &str, contents: &str) {
+    let saved = local_storage()
+        .map(|storage| storage.set_item(&format!("earth_escape.{}", key), contents).is_ok())
+        .unwrap_or(false);
+
+    if !saved {
+        bevy::log::warn!("Could not save {} to local storage", key);
+    }
+}
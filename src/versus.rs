@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::{
+    audio::{PlaySound, SoundEffect},
+    controls::InputAction,
+    coop::MAX_PLAYERS,
+    particles::{ParticleBurst, ParticleEffect},
+    replay::GameTick,
+    CenterMessageText, ChasingEnemy, Downed, PlayArea, Player, PlayerDied, PlayerHealth, PlayerSlot, RestartRun,
+    SizeScale, SubCenterText,
+};
+
+// Versus puts two Earths on the same screen like co-op, but against each other. Either
+// player can let out a repulse pulse that shoves the meteors around them away, and from
+// then on every meteor goes after the other player instead. The round ends as soon as
+// only one Earth has hearts left, and that player wins it.
+
+// Seconds before the same player can pulse again
+const PULSE_COOLDOWN: f32 = 3.;
+// How far a pulse reaches, as a fraction of the play area's width
+const PULSE_RADIUS: f32 = 0.25;
+// The push given to a meteor with a `SizeScale` of 1 right next to the pulse. It falls
+// off to nothing at the edge of the pulse, and bigger meteors are pushed less.
+const PULSE_STRENGTH: f32 = 900.;
+
+// Picked in the menu along with the player count, see `menu_input`
+#[derive(Default)]
+pub struct Versus {
+    pub enabled: bool,
+    // Rounds won by each player since versus was picked
+    pub wins: [u32; MAX_PLAYERS],
+    // The player every meteor is after, set by the last pulse. Until someone pulses
+    // they go after whoever is closest, like in co-op.
+    pub target: Option<usize>,
+    cooldowns: [f32; MAX_PLAYERS],
+    // A pulse only goes off when the key goes down, holding it does nothing
+    was_held: [bool; MAX_PLAYERS],
+}
+
+pub struct VersusPlugin;
+
+impl Plugin for VersusPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Versus>()
+            .add_system(reset_versus_round)
+            .add_system(repulse)
+            .add_system(announce_winner.label("announce_winner").after("calculate_health"));
+    }
+}
+
+fn reset_versus_round(mut restart_events: EventReader<RestartRun>, mut versus: ResMut<Versus>) {
+    if restart_events.iter().count() > 0 {
+        versus.target = None;
+        versus.cooldowns = [0.; MAX_PLAYERS];
+        versus.was_held = [false; MAX_PLAYERS];
+    }
+}
+
+// Reads the actions from `GameTick` like `player_movement`, so pulses are part of the replay
+fn repulse(
+    tick: Res<GameTick>,
+    play_area: Res<PlayArea>,
+    mut versus: ResMut<Versus>,
+    player_query: Query<(&PlayerSlot, &Transform), (With<Player>, Without<Downed>)>,
+    mut chaser_query: Query<(&Transform, &SizeScale, &mut Velocity), (With<ChasingEnemy>, Without<Player>)>,
+    mut bursts: EventWriter<ParticleBurst>,
+    mut sounds: EventWriter<PlaySound>,
+) {
+    if !versus.enabled || !tick.running {
+        return;
+    }
+
+    let delta = tick.delta.as_secs_f32();
+    let radius = play_area.0.x * PULSE_RADIUS;
+    let players: Vec<(usize, Vec2)> = player_query
+        .iter()
+        .map(|(PlayerSlot(slot), transform)| (*slot, transform.translation.truncate()))
+        .collect();
+
+    for &(slot, position) in players.iter() {
+        let held = tick.actions[slot].contains(InputAction::Repulse);
+        let just_pressed = held && !versus.was_held[slot];
+        versus.was_held[slot] = held;
+        versus.cooldowns[slot] = (versus.cooldowns[slot] - delta).max(0.);
+        if !just_pressed || versus.cooldowns[slot] > 0. {
+            continue;
+        }
+        versus.cooldowns[slot] = PULSE_COOLDOWN;
+
+        for (transform, SizeScale(size_scale), mut velocity) in chaser_query.iter_mut() {
+            let offset = transform.translation.truncate() - position;
+            let distance = offset.length();
+            if distance >= radius || distance == 0. {
+                continue;
+            }
+            let push = offset / distance * PULSE_STRENGTH * (1. - distance / radius) / size_scale;
+            velocity.linear += push.extend(0.);
+        }
+
+        // Whoever is closest to the pulse is the one it was aimed at
+        versus.target = players
+            .iter()
+            .filter(|(other, _)| *other != slot)
+            .min_by(|a, b| {
+                a.1.distance_squared(position)
+                    .partial_cmp(&b.1.distance_squared(position))
+                    .unwrap()
+            })
+            .map(|(other, _)| *other);
+
+        bursts.send(ParticleBurst {
+            effect: ParticleEffect::Pulse,
+            position,
+            scale: 1.,
+        });
+        sounds.send(PlaySound(SoundEffect::Pulse));
+    }
+}
+
+// `calculate_health` ends a versus round as soon as one Earth is left, this says who won
+fn announce_winner(
+    player_died: Res<PlayerDied>,
+    mut versus: ResMut<Versus>,
+    player_query: Query<(&PlayerSlot, &PlayerHealth)>,
+    mut center_text: Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>)>,
+    mut sub_center_text: Query<&mut Text, (With<SubCenterText>, Without<CenterMessageText>)>,
+) {
+    if !versus.enabled || !player_died.is_changed() || !player_died.0 {
+        return;
+    }
+
+    let winner = player_query
+        .iter()
+        .find(|(_, health)| health.0 > 0)
+        .map(|(PlayerSlot(slot), _)| *slot);
+
+    let message = match winner {
+        Some(slot) => {
+            versus.wins[slot] += 1;
+            format!("Player {} Wins", slot + 1)
+        }
+        // Both went down on the same frame
+        None => String::from("Draw"),
+    };
+
+    for mut text in center_text.iter_mut() {
+        text.sections[0].value = message.clone();
+    }
+    for mut text in sub_center_text.iter_mut() {
+        text.sections[0].value = format!(
+            "Player one {} - {} Player two\nPress R for a rematch or M for menu",
+            versus.wins[0], versus.wins[1],
+        );
+    }
+}